@@ -0,0 +1,70 @@
+use std::{env, fs, io, path::Path, path::PathBuf};
+
+use crate::eyre::{eyre, Result, WrapErr};
+use serde::Deserialize;
+
+const SERVER_VAR: &str = "PIGEON_SERVER";
+const USERNAME_VAR: &str = "PIGEON_USERNAME";
+const PASSWORD_VAR: &str = "PIGEON_PASSWORD";
+const DEFAULT_SERVER: &str = "http://localhost:3000";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => read(path)?.ok_or_else(|| eyre!("{} does not exist", path.display()))?,
+            None => match default_path() {
+                Some(path) => read(&path)?.unwrap_or_default(),
+                None => Self::default(),
+            },
+        };
+
+        if let Ok(server) = env::var(SERVER_VAR) {
+            config.server = Some(server);
+        }
+        if let Ok(username) = env::var(USERNAME_VAR) {
+            config.username = Some(username);
+        }
+        if let Ok(password) = env::var(PASSWORD_VAR) {
+            config.password = Some(password);
+        }
+
+        Ok(config)
+    }
+
+    pub fn server(&self) -> &str {
+        self.server.as_deref().unwrap_or(DEFAULT_SERVER)
+    }
+
+    pub fn credentials(&self) -> Result<(&str, &str)> {
+        let username = self.username.as_deref().ok_or_else(|| {
+            eyre!("no username configured, set {USERNAME_VAR} or `username` in the config file")
+        })?;
+        let password = self.password.as_deref().ok_or_else(|| {
+            eyre!("no password configured, set {PASSWORD_VAR} or `password` in the config file")
+        })?;
+        Ok((username, password))
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("pigeon").join("config.toml"))
+}
+
+fn read(path: &Path) -> Result<Option<Config>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).wrap_err_with(|| format!("reading {}", path.display())),
+    };
+    toml::from_str(&contents)
+        .map(Some)
+        .wrap_err_with(|| format!("parsing {}", path.display()))
+}
@@ -0,0 +1 @@
+pub use color_eyre::eyre::*;
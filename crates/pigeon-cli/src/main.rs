@@ -0,0 +1,392 @@
+mod config;
+mod eyre;
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
+use pigeon_client::{Client, ErrorKind, Message, Preferences, SearchParams};
+
+use config::Config;
+use eyre::{Result, WrapErr};
+
+const PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "pigeon",
+    version,
+    about = "Command-line client for a pigeon server"
+)]
+struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "Config file [default: <config dir>/pigeon/config.toml]"
+    )]
+    config: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        help = "Server URL, overrides the config file and PIGEON_SERVER"
+    )]
+    server: Option<String>,
+    #[arg(long, global = true, help = "Print one JSON object per line")]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    #[command(about = "Register the configured username and password")]
+    Register,
+    #[command(about = "Send a message to users or a channel")]
+    Send {
+        #[arg(
+            long,
+            value_delimiter = ',',
+            required_unless_present = "channel",
+            conflicts_with = "channel"
+        )]
+        to: Vec<String>,
+        #[arg(long)]
+        channel: Option<u64>,
+        #[arg(long, help = "File to attach, can be repeated")]
+        attach: Vec<PathBuf>,
+        content: String,
+    },
+    #[command(about = "Save a message's attachment, or print it to stdout")]
+    Download {
+        id: u64,
+        hash: String,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Reply to a message in its conversation")]
+    Reply { id: u64, content: String },
+    #[command(about = "Print a message's thread, starting with its root")]
+    Thread {
+        id: u64,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    #[command(about = "React to a message with an emoji")]
+    React { id: u64, emoji: String },
+    #[command(about = "Take back one of your reactions")]
+    Unreact { id: u64, emoji: String },
+    #[command(about = "Mark messages as read")]
+    Ack {
+        #[arg(required = true)]
+        ids: Vec<u64>,
+    },
+    #[command(about = "Show who got and read one of your messages")]
+    Receipts { id: u64 },
+    #[command(about = "Show or change your preferences")]
+    Preferences {
+        #[arg(long, help = "Whether authors see when you've read their messages")]
+        read_receipts: Option<bool>,
+    },
+    #[command(about = "Replace the content of one of your messages")]
+    Edit { id: u64, content: String },
+    #[command(about = "Delete one of your messages")]
+    Delete { id: u64 },
+    #[command(about = "Print messages after a cursor")]
+    Inbox {
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    #[command(about = "Search your messages, quoting words to match them as a phrase")]
+    Search {
+        query: String,
+        #[arg(long)]
+        author: Option<String>,
+        #[arg(long, help = "Unix timestamp of the earliest message to match")]
+        since: Option<u64>,
+        #[arg(long, help = "Unix timestamp of the latest message to match")]
+        until: Option<u64>,
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    #[command(about = "Follow new messages as they arrive")]
+    Tail {
+        #[arg(long)]
+        since: Option<u64>,
+    },
+    #[command(about = "List the channels you are a member of")]
+    Channels,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let cli = Cli::parse();
+    let mut config = Config::load(cli.config.as_deref())?;
+    if cli.server.is_some() {
+        config.server = cli.server;
+    }
+    let client = Client::new(config.server());
+    let (username, password) = config.credentials()?;
+
+    let result = run(cli.command, cli.json, &client, username, password).await;
+    // Every login is a session the server keeps for a day, so scripted calls don't leave theirs.
+    let logged_out = match client.session() {
+        Some(_) => client.logout().await,
+        None => Ok(()),
+    };
+    result?;
+    Ok(logged_out?)
+}
+
+async fn run(
+    command: Command,
+    json: bool,
+    client: &Client,
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    if !matches!(command, Command::Register) {
+        client.login(username, password).await?;
+    }
+
+    match command {
+        Command::Register => {
+            client.register(username, password).await?;
+            if !json {
+                println!("Registered {username}");
+            }
+        }
+        Command::Send {
+            to,
+            channel,
+            attach,
+            content,
+        } => {
+            let mut attachments = Vec::new();
+            for path in attach {
+                let contents =
+                    fs::read(&path).wrap_err_with(|| format!("reading {}", path.display()))?;
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                attachments.push(client.upload(&name, contents).await?);
+            }
+            let message = client
+                .send(Message {
+                    content,
+                    recipients: to,
+                    channel,
+                    attachments,
+                    ..Default::default()
+                })
+                .await?;
+            print_message(&message, json)?;
+        }
+        Command::Download { id, hash, output } => {
+            let contents = client.download(id, &hash).await?;
+            match output {
+                Some(path) => {
+                    fs::write(&path, contents)
+                        .wrap_err_with(|| format!("writing {}", path.display()))?;
+                }
+                None => io::stdout().write_all(&contents)?,
+            }
+        }
+        Command::Reply { id, content } => {
+            print_message(&client.reply(id, &content).await?, json)?;
+        }
+        Command::Thread { id, limit } => {
+            let mut after = 0;
+            let mut remaining = limit.unwrap_or(usize::MAX);
+            let mut root_printed = false;
+            while remaining > 0 {
+                let thread = client
+                    .thread(id, after, Some(remaining.min(PAGE_SIZE)))
+                    .await?;
+                if !root_printed {
+                    print_message(&thread.root, json)?;
+                    root_printed = true;
+                }
+                let Some(last) = thread.replies.last() else {
+                    break;
+                };
+                after = last.id;
+                remaining -= thread.replies.len();
+                for reply in &thread.replies {
+                    print_message(reply, json)?;
+                }
+            }
+        }
+        Command::React { id, emoji } => {
+            print_message(&client.react(id, &emoji).await?, json)?;
+        }
+        Command::Unreact { id, emoji } => {
+            print_message(&client.unreact(id, &emoji).await?, json)?;
+        }
+        Command::Search {
+            query,
+            author,
+            since,
+            until,
+            offset,
+            limit,
+        } => {
+            let params = SearchParams {
+                query,
+                author,
+                since,
+                until,
+                offset,
+                limit,
+            };
+            let results = client.search(&params).await?;
+            for message in &results.messages {
+                print_message(message, json)?;
+            }
+            if !json {
+                eprintln!(
+                    "Showing {} of {} matches",
+                    results.messages.len(),
+                    results.total
+                );
+            }
+        }
+        Command::Ack { ids } => client.ack(&ids).await?,
+        Command::Receipts { id } => {
+            for receipt in client.receipts(id).await? {
+                if json {
+                    println!("{}", serde_json::to_string(&receipt)?);
+                } else {
+                    let state = match (receipt.read_at, receipt.delivered_at) {
+                        (Some(at), _) => format!("read at {at}"),
+                        (None, Some(at)) => format!("delivered at {at}"),
+                        (None, None) => "not delivered".to_string(),
+                    };
+                    println!("{}: {}", receipt.user, state);
+                }
+            }
+        }
+        Command::Preferences { read_receipts } => {
+            let preferences = match read_receipts {
+                Some(read_receipts) => {
+                    client
+                        .set_preferences(&Preferences { read_receipts })
+                        .await?
+                }
+                None => client.preferences().await?,
+            };
+            if json {
+                println!("{}", serde_json::to_string(&preferences)?);
+            } else {
+                println!("read_receipts = {}", preferences.read_receipts);
+            }
+        }
+        Command::Edit { id, content } => {
+            print_message(&client.edit(id, &content).await?, json)?;
+        }
+        Command::Delete { id } => {
+            print_message(&client.delete(id).await?, json)?;
+        }
+        Command::Inbox { since, limit } => {
+            let mut after = since;
+            let mut remaining = limit.unwrap_or(usize::MAX);
+            while remaining > 0 {
+                let page = client.recv(after, Some(remaining.min(PAGE_SIZE))).await?;
+                let Some(last) = page.last() else { break };
+                after = last.revision;
+                remaining -= page.len();
+                for message in &page {
+                    print_message(message, json)?;
+                }
+            }
+        }
+        Command::Tail { since } => {
+            let mut after = match since {
+                Some(since) => since,
+                None => client.cursor().await?,
+            };
+            // Stopping on Ctrl-C rather than dying on it still logs out.
+            let ctrl_c = tokio::signal::ctrl_c();
+            tokio::pin!(ctrl_c);
+            loop {
+                let mut events = client.stream(after)?;
+                loop {
+                    let event = tokio::select! {
+                        event = events.next() => event,
+                        _ = &mut ctrl_c => return Ok(()),
+                    };
+                    match event {
+                        None => return Ok(()),
+                        Some(Err(err)) if err.kind() == Some(&ErrorKind::InvalidSession) => break,
+                        Some(event) => {
+                            if let Some(message) = event?.message() {
+                                after = after.max(message.revision);
+                                print_message(message, json)?;
+                            }
+                        }
+                    }
+                }
+                // The stream gives up once the session expires, so pick up where it left off.
+                client.login(username, password).await?;
+            }
+        }
+        Command::Channels => {
+            for channel in client.channels().await? {
+                if json {
+                    println!("{}", serde_json::to_string(&channel)?);
+                } else {
+                    let members = channel.members.into_iter().collect::<Vec<_>>();
+                    println!("#{} {} ({})", channel.id, channel.name, members.join(", "));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_message(message: &Message, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string(message)?);
+    } else {
+        let target = match message.channel {
+            Some(channel) => format!("#{channel}"),
+            None => message.recipients.join(", "),
+        };
+        let content = match (message.deleted, message.edited_at) {
+            (true, _) => "(deleted)".to_string(),
+            (false, Some(_)) => format!("{} (edited)", message.content),
+            (false, None) => message.content.clone(),
+        };
+        let reply = match message.reply_to {
+            Some(parent) => format!(" (re {parent})"),
+            None => String::new(),
+        };
+        let reactions = message
+            .reactions
+            .iter()
+            .map(|reaction| format!(" {} {}", reaction.emoji, reaction.count))
+            .collect::<String>();
+        println!(
+            "[{}] {} -> {}{}: {}{}",
+            message.id, message.author, target, reply, content, reactions
+        );
+        for attachment in &message.attachments {
+            println!(
+                "    {} {} ({}, {} bytes)",
+                attachment.hash, attachment.name, attachment.mime, attachment.size
+            );
+        }
+    }
+    Ok(())
+}
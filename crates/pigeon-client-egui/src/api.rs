@@ -0,0 +1,145 @@
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+};
+
+use eframe::egui;
+use pigeon_client::{Client, Result};
+use tokio::sync::{mpsc as tokio_mpsc, Mutex};
+
+pub use pigeon_client::{Channel, Message, Session};
+
+const PAGE_SIZE: usize = 500;
+
+#[derive(Debug)]
+pub enum Request {
+    Register { username: String, password: String },
+    Login { username: String, password: String },
+    Send(Message),
+    React { id: u64, emoji: String, added: bool },
+    Poll,
+    Channels,
+    CreateChannel { name: String, members: Vec<String> },
+}
+
+#[derive(Debug)]
+pub enum Response {
+    LoggedIn(Session),
+    Sent(Message),
+    Updated(Message),
+    Messages(Vec<Message>),
+    Channels(Vec<Channel>),
+    Error(String),
+}
+
+pub struct Worker {
+    requests: tokio_mpsc::UnboundedSender<Request>,
+    responses: mpsc::Receiver<Response>,
+}
+
+impl Worker {
+    pub fn spawn(server: String, ctx: egui::Context) -> Self {
+        let (request_tx, mut request_rx) = tokio_mpsc::unbounded_channel();
+        let (response_tx, response_rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("failed to start the network runtime");
+            let api = Arc::new(Api {
+                client: Client::new(server),
+                after: Mutex::new(None),
+            });
+
+            runtime.block_on(async move {
+                while let Some(request) = request_rx.recv().await {
+                    let api = Arc::clone(&api);
+                    let response_tx = response_tx.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        let response = api
+                            .handle(request)
+                            .await
+                            .unwrap_or_else(|err| Response::Error(err.to_string()));
+                        if response_tx.send(response).is_ok() {
+                            ctx.request_repaint();
+                        }
+                    });
+                }
+            });
+        });
+
+        Self {
+            requests: request_tx,
+            responses: response_rx,
+        }
+    }
+
+    pub fn send(&self, request: Request) {
+        let _ = self.requests.send(request);
+    }
+
+    pub fn responses(&self) -> impl Iterator<Item = Response> + '_ {
+        self.responses.try_iter()
+    }
+}
+
+struct Api {
+    client: Client,
+    /// Revision of the last message polled, or `None` until the first poll after logging in.
+    after: Mutex<Option<u64>>,
+}
+
+impl Api {
+    async fn handle(&self, request: Request) -> Result<Response> {
+        match request {
+            Request::Register { username, password } => {
+                self.client.register(&username, &password).await?;
+                self.login(&username, &password).await
+            }
+            Request::Login { username, password } => self.login(&username, &password).await,
+            Request::Send(message) => self.client.send(message).await.map(Response::Sent),
+            Request::React { id, emoji, added } => {
+                let message = if added {
+                    self.client.react(id, &emoji).await?
+                } else {
+                    self.client.unreact(id, &emoji).await?
+                };
+                Ok(Response::Updated(message))
+            }
+            Request::Poll => {
+                let mut cursor = self.after.lock().await;
+                // The first poll after logging in also picks up the user's side of every
+                // conversation, which the inbox doesn't have.
+                let mut messages = match *cursor {
+                    None => self.client.authored().await?,
+                    Some(_) => Vec::new(),
+                };
+                let after = cursor.get_or_insert(0);
+                loop {
+                    let page = self.client.recv(*after, Some(PAGE_SIZE)).await?;
+                    let done = page.len() < PAGE_SIZE;
+                    if let Some(last) = page.last() {
+                        *after = last.revision;
+                    }
+                    messages.extend(page);
+                    if done {
+                        break Ok(Response::Messages(messages));
+                    }
+                }
+            }
+            Request::Channels => self.client.channels().await.map(Response::Channels),
+            Request::CreateChannel { name, members } => {
+                self.client.create_channel(&name, &members).await?;
+                self.client.channels().await.map(Response::Channels)
+            }
+        }
+    }
+
+    async fn login(&self, username: &str, password: &str) -> Result<Response> {
+        let session = self.client.login(username, password).await?;
+        *self.after.lock().await = None;
+        Ok(Response::LoggedIn(session))
+    }
+}
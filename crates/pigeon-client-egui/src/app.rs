@@ -0,0 +1,381 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
+
+use eframe::egui;
+
+use pigeon_client::conversation::{split_users, Conversation};
+
+use crate::api::{Channel, Message, Request, Response, Session, Worker};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+struct LoginForm {
+    username: String,
+    password: String,
+    pending: bool,
+}
+
+struct Chat {
+    session: Session,
+    channels: BTreeMap<u64, Channel>,
+    conversations: BTreeMap<Conversation, BTreeMap<u64, Message>>,
+    selected: Option<Conversation>,
+    compose: String,
+    replying: Option<u64>,
+    new_direct: String,
+    new_channel: String,
+    last_poll: Option<Instant>,
+    polling: bool,
+}
+
+impl Chat {
+    fn new(session: Session) -> Self {
+        Self {
+            session,
+            channels: BTreeMap::new(),
+            conversations: BTreeMap::new(),
+            selected: None,
+            compose: String::new(),
+            replying: None,
+            new_direct: String::new(),
+            new_channel: String::new(),
+            last_poll: None,
+            polling: false,
+        }
+    }
+
+    fn add_message(&mut self, message: Message) {
+        self.conversations
+            .entry(Conversation::of(&message, &self.session.username))
+            .or_default()
+            .insert(message.id, message);
+    }
+
+    fn quote(&self, conversation: &Conversation, id: u64) -> String {
+        match self
+            .conversations
+            .get(conversation)
+            .and_then(|messages| messages.get(&id))
+        {
+            Some(parent) if parent.deleted => format!("> {}: (deleted)", parent.author),
+            Some(parent) => format!("> {}: {}", parent.author, parent.content),
+            None => "> an earlier message".to_string(),
+        }
+    }
+}
+
+enum Screen {
+    Login(LoginForm),
+    Chat(Box<Chat>),
+}
+
+pub struct App {
+    worker: Worker,
+    screen: Screen,
+    error: Option<String>,
+}
+
+impl App {
+    pub fn new(cc: &eframe::CreationContext<'_>, server: String) -> Self {
+        Self {
+            worker: Worker::spawn(server, cc.egui_ctx.clone()),
+            screen: Screen::Login(LoginForm::default()),
+            error: None,
+        }
+    }
+
+    fn handle(&mut self, response: Response) {
+        match (&mut self.screen, response) {
+            (_, Response::LoggedIn(session)) => {
+                self.screen = Screen::Chat(Box::new(Chat::new(session)));
+                self.worker.send(Request::Channels);
+                self.error = None;
+            }
+            (Screen::Login(form), Response::Error(error)) => {
+                form.pending = false;
+                self.error = Some(error);
+            }
+            (Screen::Chat(chat), Response::Error(error)) => {
+                chat.polling = false;
+                self.error = Some(error);
+            }
+            (Screen::Chat(chat), Response::Sent(message)) => {
+                chat.selected = Some(Conversation::of(&message, &chat.session.username));
+                chat.add_message(message);
+            }
+            (Screen::Chat(chat), Response::Updated(message)) => chat.add_message(message),
+            (Screen::Chat(chat), Response::Messages(messages)) => {
+                chat.polling = false;
+                for message in messages {
+                    chat.add_message(message);
+                }
+            }
+            (Screen::Chat(chat), Response::Channels(channels)) => {
+                for channel in &channels {
+                    chat.conversations
+                        .entry(Conversation::Channel(channel.id))
+                        .or_default();
+                }
+                chat.channels = channels
+                    .into_iter()
+                    .map(|channel| (channel.id, channel))
+                    .collect();
+            }
+            (Screen::Login(_), _) => {}
+        }
+    }
+
+    fn login_screen(ui: &mut egui::Ui, form: &mut LoginForm, worker: &Worker) {
+        ui.vertical_centered(|ui| {
+            ui.add_space(ui.available_height() / 4.0);
+            ui.heading("Pigeon");
+            ui.add_space(16.0);
+
+            ui.add(egui::TextEdit::singleline(&mut form.username).hint_text("Username"));
+            let password = ui.add(
+                egui::TextEdit::singleline(&mut form.password)
+                    .hint_text("Password")
+                    .password(true),
+            );
+            ui.add_space(8.0);
+
+            ui.add_enabled_ui(!form.pending && !form.username.is_empty(), |ui| {
+                let submitted = password.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+                if ui.button("Log in").clicked() || submitted {
+                    form.pending = true;
+                    worker.send(Request::Login {
+                        username: form.username.clone(),
+                        password: form.password.clone(),
+                    });
+                }
+                if ui.button("Register").clicked() {
+                    form.pending = true;
+                    worker.send(Request::Register {
+                        username: form.username.clone(),
+                        password: form.password.clone(),
+                    });
+                }
+            });
+            if form.pending {
+                ui.spinner();
+            }
+        });
+    }
+
+    fn conversation_list(ui: &mut egui::Ui, chat: &mut Chat, worker: &Worker) {
+        ui.heading(&chat.session.username);
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .max_height(ui.available_height() - 120.0)
+            .show(ui, |ui| {
+                let titles = chat
+                    .conversations
+                    .keys()
+                    .map(|conversation| (conversation.clone(), conversation.title(&chat.channels)))
+                    .collect::<Vec<_>>();
+                for (conversation, title) in titles {
+                    let selected = chat.selected.as_ref() == Some(&conversation);
+                    if ui.selectable_label(selected, title).clicked() {
+                        chat.selected = Some(conversation);
+                        chat.replying = None;
+                    }
+                }
+            });
+
+        ui.separator();
+        ui.add(egui::TextEdit::singleline(&mut chat.new_direct).hint_text("alice, bob"));
+        if ui.button("New conversation").clicked() && !chat.new_direct.trim().is_empty() {
+            let users = split_users(&chat.new_direct)
+                .filter(|user| *user != chat.session.username)
+                .collect::<BTreeSet<_>>();
+            let conversation = Conversation::Direct(users);
+            chat.conversations.entry(conversation.clone()).or_default();
+            chat.selected = Some(conversation);
+            chat.replying = None;
+            chat.new_direct.clear();
+        }
+
+        ui.add(egui::TextEdit::singleline(&mut chat.new_channel).hint_text("Channel name"));
+        if ui.button("New channel").clicked() && !chat.new_channel.trim().is_empty() {
+            worker.send(Request::CreateChannel {
+                name: chat.new_channel.trim().to_string(),
+                members: Vec::new(),
+            });
+            chat.new_channel.clear();
+        }
+    }
+
+    fn compose(ui: &mut egui::Ui, chat: &mut Chat, worker: &Worker) {
+        let conversation = match &chat.selected {
+            Some(conversation) => conversation.clone(),
+            None => return,
+        };
+
+        if let Some(parent) = chat.replying {
+            ui.horizontal(|ui| {
+                ui.weak(format!("Replying {}", chat.quote(&conversation, parent)));
+                if ui.small_button("x").clicked() {
+                    chat.replying = None;
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            let input = ui.add(
+                egui::TextEdit::singleline(&mut chat.compose)
+                    .hint_text("Message")
+                    .desired_width(ui.available_width() - 60.0),
+            );
+            let submitted = input.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            if (ui.button("Send").clicked() || submitted) && !chat.compose.trim().is_empty() {
+                let (recipients, channel) = match conversation {
+                    Conversation::Channel(id) => (Vec::new(), Some(id)),
+                    Conversation::Direct(users) if users.is_empty() => {
+                        (vec![chat.session.username.clone()], None)
+                    }
+                    Conversation::Direct(users) => (users.into_iter().collect(), None),
+                };
+                worker.send(Request::Send(Message {
+                    author: chat.session.username.clone(),
+                    content: chat.compose.trim().to_string(),
+                    recipients,
+                    channel,
+                    reply_to: chat.replying.take(),
+                    ..Default::default()
+                }));
+                chat.compose.clear();
+                input.request_focus();
+            }
+        });
+    }
+
+    fn message_view(ui: &mut egui::Ui, chat: &mut Chat, worker: &Worker) {
+        let (conversation, messages) = match chat.selected.as_ref().and_then(|conversation| {
+            chat.conversations
+                .get(conversation)
+                .map(|messages| (conversation, messages))
+        }) {
+            Some(selected) => selected,
+            None => {
+                ui.centered_and_justified(|ui| ui.label("Pick a conversation"));
+                return;
+            }
+        };
+
+        ui.heading(conversation.title(&chat.channels));
+        if let Conversation::Channel(id) = conversation {
+            if let Some(channel) = chat.channels.get(id) {
+                let members = channel.members.iter().cloned().collect::<Vec<_>>();
+                ui.label(format!("Members: {}", members.join(", ")));
+            }
+        }
+        ui.separator();
+
+        let mut replying = None;
+        egui::ScrollArea::vertical()
+            .stick_to_bottom(true)
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                for message in messages.values() {
+                    if let Some(parent) = message.reply_to {
+                        ui.weak(chat.quote(conversation, parent));
+                    }
+                    ui.horizontal_wrapped(|ui| {
+                        ui.strong(&message.author);
+                        if message.deleted {
+                            ui.weak("Message deleted");
+                        } else {
+                            ui.label(&message.content);
+                            if message.edited_at.is_some() {
+                                ui.weak("(edited)");
+                            }
+                            for attachment in &message.attachments {
+                                ui.weak(format!("📎 {}", attachment.name));
+                            }
+                            for reaction in &message.reactions {
+                                let label = format!("{} {}", reaction.emoji, reaction.count);
+                                if ui.small_button(label).clicked() {
+                                    worker.send(Request::React {
+                                        id: message.id,
+                                        emoji: reaction.emoji.clone(),
+                                        added: !reaction.users.contains(&chat.session.username),
+                                    });
+                                }
+                            }
+                            if ui.small_button("Reply").clicked() {
+                                replying = Some(message.id);
+                            }
+                            if !message
+                                .reactions
+                                .iter()
+                                .any(|reaction| reaction.emoji == "👍")
+                                && ui.small_button("👍").clicked()
+                            {
+                                worker.send(Request::React {
+                                    id: message.id,
+                                    emoji: "👍".to_string(),
+                                    added: true,
+                                });
+                            }
+                        }
+                    });
+                }
+            });
+        if replying.is_some() {
+            chat.replying = replying;
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let responses = self.worker.responses().collect::<Vec<_>>();
+        for response in responses {
+            self.handle(response);
+        }
+
+        if let Some(error) = &self.error {
+            let mut dismissed = false;
+            egui::TopBottomPanel::top("error").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(egui::Color32::RED, error);
+                    dismissed = ui.small_button("x").clicked();
+                });
+            });
+            if dismissed {
+                self.error = None;
+            }
+        }
+
+        match &mut self.screen {
+            Screen::Login(form) => {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    Self::login_screen(ui, form, &self.worker);
+                });
+            }
+            Screen::Chat(chat) => {
+                if !chat.polling
+                    && chat
+                        .last_poll
+                        .is_none_or(|at| at.elapsed() >= POLL_INTERVAL)
+                {
+                    chat.polling = true;
+                    chat.last_poll = Some(Instant::now());
+                    self.worker.send(Request::Poll);
+                }
+                ctx.request_repaint_after(POLL_INTERVAL);
+
+                egui::SidePanel::left("conversations")
+                    .resizable(true)
+                    .show(ctx, |ui| Self::conversation_list(ui, chat, &self.worker));
+                egui::TopBottomPanel::bottom("compose")
+                    .show(ctx, |ui| Self::compose(ui, chat, &self.worker));
+                egui::CentralPanel::default()
+                    .show(ctx, |ui| Self::message_view(ui, chat, &self.worker));
+            }
+        }
+    }
+}
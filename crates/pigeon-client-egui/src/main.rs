@@ -0,0 +1,22 @@
+mod api;
+mod app;
+
+use std::env;
+
+use eframe::egui;
+
+const SERVER_VAR: &str = "PIGEON_SERVER";
+const DEFAULT_SERVER: &str = "http://localhost:3000";
+
+fn main() {
+    let server = env::var(SERVER_VAR).unwrap_or_else(|_| DEFAULT_SERVER.to_string());
+
+    eframe::run_native(
+        "Pigeon",
+        eframe::NativeOptions {
+            initial_window_size: Some(egui::vec2(900.0, 600.0)),
+            ..Default::default()
+        },
+        Box::new(|cc| Box::new(app::App::new(cc, server))),
+    );
+}
@@ -0,0 +1,176 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use pigeon_client::{Client, ErrorKind, Event};
+use tokio::sync::mpsc::UnboundedSender;
+
+pub use pigeon_client::{Channel, Message, Receipt, Session};
+
+const RELOGIN_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum Update {
+    LoggedIn(Session),
+    Message(Message),
+    Reply(Message, Arc<Message>),
+    Changed(Message),
+    Receipts(Vec<Receipt>),
+    Channel(Channel),
+    Channels(Vec<Channel>),
+    Error(String),
+}
+
+#[derive(Clone)]
+pub struct Api {
+    client: Arc<Client>,
+    updates: UnboundedSender<Update>,
+}
+
+impl Api {
+    pub fn new(server: &str, updates: UnboundedSender<Update>) -> Self {
+        Self {
+            client: Arc::new(Client::new(server)),
+            updates,
+        }
+    }
+
+    pub fn register(&self, username: String, password: String) {
+        let api = self.clone();
+        tokio::spawn(async move {
+            match api.client.register(&username, &password).await {
+                Ok(()) => api.login_and_follow(username, password).await,
+                Err(err) => api.error(err),
+            }
+        });
+    }
+
+    pub fn login(&self, username: String, password: String) {
+        let api = self.clone();
+        tokio::spawn(async move { api.login_and_follow(username, password).await });
+    }
+
+    pub fn send(&self, recipients: Vec<String>, channel: Option<u64>, content: String) {
+        let api = self.clone();
+        tokio::spawn(async move {
+            let result = match channel {
+                Some(channel) => api.client.send_to_channel(channel, &content).await,
+                None => api.client.send_to(&recipients, &content).await,
+            };
+            match result {
+                Ok(message) => api.update(Update::Message(message)),
+                Err(err) => api.error(err),
+            }
+        });
+    }
+
+    pub fn reply(&self, parent: u64, content: String) {
+        let api = self.clone();
+        tokio::spawn(async move {
+            match api.client.reply(parent, &content).await {
+                Ok(message) => api.update(Update::Message(message)),
+                Err(err) => api.error(err),
+            }
+        });
+    }
+
+    pub fn react(&self, id: u64, emoji: String) {
+        let api = self.clone();
+        tokio::spawn(async move {
+            match api.client.react(id, &emoji).await {
+                Ok(message) => api.update(Update::Changed(message)),
+                Err(err) => api.error(err),
+            }
+        });
+    }
+
+    pub fn ack(&self, messages: Vec<u64>) {
+        let api = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = api.client.ack(&messages).await {
+                api.error(err);
+            }
+        });
+    }
+
+    pub fn create_channel(&self, name: String) {
+        let api = self.clone();
+        tokio::spawn(async move {
+            match api.client.create_channel(&name, &[]).await {
+                Ok(channel) => api.update(Update::Channel(channel)),
+                Err(err) => api.error(err),
+            }
+        });
+    }
+
+    async fn login_and_follow(&self, username: String, password: String) {
+        match self.client.login(&username, &password).await {
+            Ok(session) => self.update(Update::LoggedIn(session)),
+            Err(err) => return self.error(err),
+        }
+        match self.client.channels().await {
+            Ok(channels) => self.update(Update::Channels(channels)),
+            Err(err) => self.error(err),
+        }
+        // The stream only replays the inbox, which leaves out the user's own messages.
+        match self.client.authored().await {
+            Ok(messages) => {
+                for message in messages.into_iter().rev() {
+                    self.update(Update::Message(message));
+                }
+            }
+            Err(err) => self.error(err),
+        }
+
+        let mut after = 0;
+        loop {
+            let mut events = match self.client.stream(after) {
+                Ok(events) => events,
+                Err(err) => return self.error(err),
+            };
+            while let Some(event) = events.next().await {
+                match event {
+                    Ok(Event::Channel { channel }) => self.update(Update::Channel(channel)),
+                    Ok(Event::Receipts { receipts, .. }) => self.update(Update::Receipts(receipts)),
+                    Ok(Event::Message { message }) => {
+                        after = after.max(message.revision);
+                        self.update(Update::Message(Arc::unwrap_or_clone(message)));
+                    }
+                    Ok(Event::Reply { message, parent }) => {
+                        after = after.max(message.revision);
+                        self.update(Update::Reply(Arc::unwrap_or_clone(message), parent));
+                    }
+                    Ok(
+                        Event::Edit { message }
+                        | Event::Delete { message }
+                        | Event::Reaction { message, .. },
+                    ) => {
+                        after = after.max(message.revision);
+                        self.update(Update::Changed(Arc::unwrap_or_clone(message)));
+                    }
+                    Err(err) => self.error(err),
+                }
+                if self.updates.is_closed() {
+                    return;
+                }
+            }
+
+            // The stream only gives up once the session is gone, e.g. after it expired.
+            while let Err(err) = self.client.login(&username, &password).await {
+                self.error(err);
+                tokio::time::sleep(RELOGIN_DELAY).await;
+            }
+        }
+    }
+
+    fn update(&self, update: Update) {
+        let _ = self.updates.send(update);
+    }
+
+    fn error(&self, err: pigeon_client::Error) {
+        let message = match err.kind() {
+            Some(ErrorKind::InvalidSession) => "Session expired, logging in again".to_string(),
+            _ => err.to_string(),
+        };
+        self.update(Update::Error(message));
+    }
+}
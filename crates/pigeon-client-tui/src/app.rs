@@ -0,0 +1,368 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use pigeon_client::conversation::{split_users, Conversation};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::api::{Api, Channel, Message, Receipt, Session, Update};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Username,
+    Password,
+}
+
+pub struct LoginForm {
+    pub username: String,
+    pub password: String,
+    pub field: Field,
+    pub pending: bool,
+}
+
+pub struct Chat {
+    pub session: Session,
+    pub channels: BTreeMap<u64, Channel>,
+    pub conversations: BTreeMap<Conversation, BTreeMap<u64, Message>>,
+    pub unread: BTreeMap<Conversation, usize>,
+    pub receipts: BTreeMap<u64, BTreeMap<String, Receipt>>,
+    pub selected: Option<Conversation>,
+    pub input: String,
+    pub scroll: usize,
+    since: u64,
+    acked: BTreeSet<u64>,
+}
+
+impl Chat {
+    fn new(session: Session) -> Self {
+        Self {
+            session,
+            channels: BTreeMap::new(),
+            conversations: BTreeMap::new(),
+            unread: BTreeMap::new(),
+            receipts: BTreeMap::new(),
+            selected: None,
+            input: String::new(),
+            scroll: 0,
+            since: now(),
+            acked: BTreeSet::new(),
+        }
+    }
+
+    fn add_message(&mut self, message: Message) {
+        let conversation = Conversation::of(&message, &self.session.username);
+        let known = self
+            .conversations
+            .get(&conversation)
+            .is_some_and(|messages| messages.contains_key(&message.id));
+        // History replayed on login isn't counted, there's no read state to tell what was seen.
+        if self.selected.as_ref() != Some(&conversation)
+            && !known
+            && message.author != self.session.username
+            && message.timestamp >= self.since
+        {
+            *self.unread.entry(conversation.clone()).or_default() += 1;
+        }
+        if self.selected.is_none() {
+            self.selected = Some(conversation.clone());
+        }
+        self.conversations
+            .entry(conversation)
+            .or_default()
+            .insert(message.id, message);
+    }
+
+    fn replace_message(&mut self, message: Message) {
+        self.conversations
+            .entry(Conversation::of(&message, &self.session.username))
+            .or_default()
+            .insert(message.id, message);
+    }
+
+    fn add_channel(&mut self, channel: Channel) {
+        let conversation = Conversation::Channel(channel.id);
+        if !channel.members.contains(&self.session.username) {
+            self.conversations.remove(&conversation);
+            self.unread.remove(&conversation);
+            if self.selected.as_ref() == Some(&conversation) {
+                self.selected = self.conversations.keys().next().cloned();
+            }
+            self.channels.remove(&channel.id);
+            return;
+        }
+        self.conversations.entry(conversation).or_default();
+        self.channels.insert(channel.id, channel);
+    }
+
+    fn add_receipt(&mut self, receipt: Receipt) {
+        self.receipts
+            .entry(receipt.message)
+            .or_default()
+            .insert(receipt.user.clone(), receipt);
+    }
+
+    /// Everything on screen in the open conversation counts as read.
+    fn acknowledge(&mut self, api: &Api) {
+        let Some(messages) = self
+            .selected
+            .as_ref()
+            .and_then(|selected| self.conversations.get(selected))
+        else {
+            return;
+        };
+        let ids = messages
+            .values()
+            .filter(|message| message.author != self.session.username)
+            .map(|message| message.id)
+            .filter(|id| !self.acked.contains(id))
+            .collect::<Vec<_>>();
+        if !ids.is_empty() {
+            self.acked.extend(&ids);
+            api.ack(ids);
+        }
+    }
+
+    fn select(&mut self, conversation: Conversation) {
+        self.unread.remove(&conversation);
+        self.selected = Some(conversation);
+        self.scroll = 0;
+    }
+
+    fn step(&mut self, forward: bool) {
+        let keys = self.conversations.keys().collect::<Vec<_>>();
+        if keys.is_empty() {
+            return;
+        }
+        let index = self
+            .selected
+            .as_ref()
+            .and_then(|selected| keys.iter().position(|key| *key == selected));
+        let index = match (index, forward) {
+            (None, _) => 0,
+            (Some(index), true) => (index + 1) % keys.len(),
+            (Some(index), false) => (index + keys.len() - 1) % keys.len(),
+        };
+        let conversation = keys[index].clone();
+        self.select(conversation);
+    }
+
+    fn latest_from_others(&self) -> Option<&Message> {
+        self.conversations
+            .get(self.selected.as_ref()?)?
+            .values()
+            .rev()
+            .find(|message| message.author != self.session.username && !message.deleted)
+    }
+
+    fn submit(&mut self, api: &Api) -> Option<String> {
+        let input = std::mem::take(&mut self.input);
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+
+        if let Some(users) = input.strip_prefix("/dm ") {
+            let users = split_users(users)
+                .filter(|user| *user != self.session.username)
+                .collect::<BTreeSet<_>>();
+            let conversation = Conversation::Direct(users);
+            self.conversations.entry(conversation.clone()).or_default();
+            self.select(conversation);
+            return None;
+        }
+        if let Some(name) = input.strip_prefix("/channel ") {
+            api.create_channel(name.trim().to_string());
+            return None;
+        }
+        if let Some(content) = input.strip_prefix("/reply ") {
+            let Some(parent) = self.latest_from_others() else {
+                return Some("Nothing to reply to here".to_string());
+            };
+            api.reply(parent.id, content.trim().to_string());
+            self.scroll = 0;
+            return None;
+        }
+        if let Some(emoji) = input.strip_prefix("/react ") {
+            let Some(message) = self.latest_from_others() else {
+                return Some("Nothing to react to here".to_string());
+            };
+            api.react(message.id, emoji.trim().to_string());
+            return None;
+        }
+        if input.starts_with('/') && !input.starts_with("//") {
+            return Some(format!("Unknown command {input}"));
+        }
+        let content = input.strip_prefix('/').unwrap_or(input).to_string();
+
+        let (recipients, channel) = match self.selected.clone()? {
+            Conversation::Channel(id) => (Vec::new(), Some(id)),
+            Conversation::Direct(users) if users.is_empty() => {
+                (vec![self.session.username.clone()], None)
+            }
+            Conversation::Direct(users) => (users.into_iter().collect(), None),
+        };
+        api.send(recipients, channel, content);
+        self.scroll = 0;
+        None
+    }
+}
+
+pub enum Screen {
+    Login(LoginForm),
+    Chat(Box<Chat>),
+}
+
+pub struct App {
+    api: Api,
+    pub screen: Screen,
+    pub status: Option<String>,
+    pub quit: bool,
+}
+
+impl App {
+    pub fn new(api: Api, username: String, password: String) -> Self {
+        let pending = !username.is_empty() && !password.is_empty();
+        if pending {
+            api.login(username.clone(), password.clone());
+        }
+        Self {
+            api,
+            screen: Screen::Login(LoginForm {
+                field: if username.is_empty() {
+                    Field::Username
+                } else {
+                    Field::Password
+                },
+                username,
+                password,
+                pending,
+            }),
+            status: None,
+            quit: false,
+        }
+    }
+
+    pub fn update(&mut self, update: Update) {
+        match (&mut self.screen, update) {
+            (_, Update::LoggedIn(session)) => {
+                if !matches!(&self.screen, Screen::Chat(chat) if chat.session.username == session.username)
+                {
+                    self.screen = Screen::Chat(Box::new(Chat::new(session)));
+                }
+                self.status = None;
+            }
+            (Screen::Login(form), Update::Error(error)) => {
+                form.pending = false;
+                self.status = Some(error);
+            }
+            (Screen::Chat(_), Update::Error(error)) => self.status = Some(error),
+            (Screen::Chat(chat), Update::Message(message)) => chat.add_message(message),
+            (Screen::Chat(chat), Update::Reply(message, parent)) => {
+                let conversation = Conversation::of(&message, &chat.session.username);
+                if parent.author == chat.session.username
+                    && message.author != chat.session.username
+                    && message.timestamp >= chat.since
+                    && chat.selected.as_ref() != Some(&conversation)
+                {
+                    self.status = Some(format!(
+                        "{} replied to you in {}",
+                        message.author,
+                        conversation.title(&chat.channels)
+                    ));
+                }
+                chat.add_message(message);
+            }
+            (Screen::Chat(chat), Update::Changed(message)) => chat.replace_message(message),
+            (Screen::Chat(chat), Update::Receipts(receipts)) => {
+                for receipt in receipts {
+                    chat.add_receipt(receipt);
+                }
+            }
+            (Screen::Chat(chat), Update::Channel(channel)) => chat.add_channel(channel),
+            (Screen::Chat(chat), Update::Channels(channels)) => {
+                for channel in channels {
+                    chat.add_channel(channel);
+                }
+            }
+            (Screen::Login(_), _) => {}
+        }
+        if let Screen::Chat(chat) = &mut self.screen {
+            chat.acknowledge(&self.api);
+        }
+    }
+
+    fn submit_login(&mut self, register: bool) {
+        let Screen::Login(form) = &mut self.screen else {
+            return;
+        };
+        if form.pending || form.username.is_empty() {
+            return;
+        }
+        form.pending = true;
+        let (username, password) = (form.username.clone(), form.password.clone());
+        if register {
+            self.api.register(username, password);
+        } else {
+            self.api.login(username, password);
+        }
+        self.status = None;
+    }
+
+    pub fn key(&mut self, key: KeyEvent) {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if key.code == KeyCode::Esc || (ctrl && key.code == KeyCode::Char('c')) {
+            self.quit = true;
+            return;
+        }
+
+        match &mut self.screen {
+            Screen::Login(form) => {
+                let field = match form.field {
+                    Field::Username => &mut form.username,
+                    Field::Password => &mut form.password,
+                };
+                match key.code {
+                    KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+                        form.field = match form.field {
+                            Field::Username => Field::Password,
+                            Field::Password => Field::Username,
+                        };
+                    }
+                    KeyCode::Enter => self.submit_login(false),
+                    KeyCode::Char('r') if ctrl => self.submit_login(true),
+                    KeyCode::Backspace => {
+                        field.pop();
+                    }
+                    KeyCode::Char(c) if !ctrl => field.push(c),
+                    _ => {}
+                }
+            }
+            Screen::Chat(chat) => match key.code {
+                KeyCode::Up | KeyCode::BackTab => {
+                    chat.step(false);
+                    chat.acknowledge(&self.api);
+                }
+                KeyCode::Down | KeyCode::Tab => {
+                    chat.step(true);
+                    chat.acknowledge(&self.api);
+                }
+                KeyCode::PageUp => chat.scroll += 10,
+                KeyCode::PageDown => chat.scroll = chat.scroll.saturating_sub(10),
+                KeyCode::Enter => self.status = chat.submit(&self.api),
+                KeyCode::Backspace => {
+                    chat.input.pop();
+                }
+                KeyCode::Char('u') if ctrl => chat.input.clear(),
+                KeyCode::Char(c) if !ctrl => chat.input.push(c),
+                _ => {}
+            },
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
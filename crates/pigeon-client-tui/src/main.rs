@@ -0,0 +1,71 @@
+mod api;
+mod app;
+mod ui;
+
+use std::{env, thread};
+
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use tokio::sync::mpsc;
+
+use api::{Api, Update};
+use app::App;
+
+const SERVER_VAR: &str = "PIGEON_SERVER";
+const USERNAME_VAR: &str = "PIGEON_USERNAME";
+const PASSWORD_VAR: &str = "PIGEON_PASSWORD";
+const DEFAULT_SERVER: &str = "http://localhost:3000";
+
+enum Input {
+    Terminal(Event),
+    Api(Box<Update>),
+}
+
+#[tokio::main]
+async fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let server = env::var(SERVER_VAR).unwrap_or_else(|_| DEFAULT_SERVER.to_string());
+    let (input_tx, mut input_rx) = mpsc::unbounded_channel();
+
+    let (update_tx, mut update_rx) = mpsc::unbounded_channel();
+    let api = Api::new(&server, update_tx);
+    let forward = input_tx.clone();
+    tokio::spawn(async move {
+        while let Some(update) = update_rx.recv().await {
+            if forward.send(Input::Api(Box::new(update))).is_err() {
+                break;
+            }
+        }
+    });
+    thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if input_tx.send(Input::Terminal(event)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut app = App::new(
+        api,
+        env::var(USERNAME_VAR).unwrap_or_default(),
+        env::var(PASSWORD_VAR).unwrap_or_default(),
+    );
+    let mut terminal = ratatui::init();
+    let result = async {
+        while !app.quit {
+            terminal.draw(|frame| ui::draw(frame, &app))?;
+            match input_rx.recv().await {
+                Some(Input::Terminal(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    app.key(key)
+                }
+                Some(Input::Terminal(_)) => {}
+                Some(Input::Api(update)) => app.update(*update),
+                None => break,
+            }
+        }
+        color_eyre::Result::<()>::Ok(())
+    }
+    .await;
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,261 @@
+use pigeon_client::conversation::Conversation;
+use ratatui::{
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use crate::{
+    api::{Message, Receipt},
+    app::{App, Chat, Field, LoginForm, Screen},
+};
+
+const LOGIN_HELP: &str = "Enter log in · Ctrl-R register · Tab switch field · Esc quit";
+const CHAT_HELP: &str =
+    "Up/Down switch · PgUp/PgDn scroll · /dm alice,bob · /channel name · /reply text · /react 👍 · Esc quit";
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+    let help = match &app.screen {
+        Screen::Login(form) => {
+            login(frame, main, form);
+            LOGIN_HELP
+        }
+        Screen::Chat(chat) => {
+            self::chat(frame, main, chat);
+            CHAT_HELP
+        }
+    };
+
+    let status_line = match &app.status {
+        Some(error) => Line::from(error.as_str()).fg(Color::Red),
+        None => Line::from(help).fg(Color::DarkGray),
+    };
+    frame.render_widget(status_line, status);
+}
+
+fn login(frame: &mut Frame, area: Rect, form: &LoginForm) {
+    let [_, area, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(8),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    let [_, area, _] = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Length(40),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+
+    let block = Block::bordered().title(if form.pending {
+        " Pigeon · connecting… "
+    } else {
+        " Pigeon "
+    });
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let [username, password] =
+        Layout::vertical([Constraint::Length(3), Constraint::Length(3)]).areas(inner);
+    let masked = "*".repeat(form.password.chars().count());
+    for (field, area, title, value) in [
+        (
+            Field::Username,
+            username,
+            " Username ",
+            form.username.as_str(),
+        ),
+        (Field::Password, password, " Password ", masked.as_str()),
+    ] {
+        let focused = form.field == field;
+        let block = Block::bordered()
+            .title(title)
+            .border_style(focus_style(focused));
+        frame.render_widget(Paragraph::new(value).block(block), area);
+        if focused {
+            frame.set_cursor_position(cursor(area, value));
+        }
+    }
+}
+
+fn chat(frame: &mut Frame, area: Rect, chat: &Chat) {
+    let [top, input] = Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).areas(area);
+    let [conversations, message_pane] =
+        Layout::horizontal([Constraint::Length(28), Constraint::Min(0)]).areas(top);
+
+    let items = chat
+        .conversations
+        .keys()
+        .map(|conversation| {
+            let title = conversation.title(&chat.channels);
+            match chat.unread.get(conversation) {
+                Some(unread) => ListItem::new(Line::from(vec![
+                    Span::raw(title).bold(),
+                    Span::raw(format!(" ({unread})")).fg(Color::Yellow),
+                ])),
+                None => ListItem::new(title),
+            }
+        })
+        .collect::<Vec<_>>();
+    let selected = chat
+        .selected
+        .as_ref()
+        .and_then(|selected| chat.conversations.keys().position(|key| key == selected));
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(Block::bordered().title(format!(" {} ", chat.session.username)))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+        conversations,
+        &mut ListState::default().with_selected(selected),
+    );
+
+    let width = usize::from(message_pane.width.saturating_sub(2));
+    let (title, lines) = match chat.selected.as_ref().and_then(|conversation| {
+        chat.conversations
+            .get(conversation)
+            .map(|messages| (conversation, messages))
+    }) {
+        Some((conversation, messages)) => {
+            let mut title = conversation.title(&chat.channels);
+            if let Conversation::Channel(id) = conversation {
+                if let Some(channel) = chat.channels.get(id) {
+                    let members = channel.members.iter().cloned().collect::<Vec<_>>();
+                    title = format!("{title} · {}", members.join(", "));
+                }
+            }
+            let lines = messages
+                .values()
+                .map(|message| {
+                    if message.deleted {
+                        return ListItem::new(wrap(&message.author, "(deleted)", width).dim());
+                    }
+                    let mut text = wrap(&message.author, &message.content, width);
+                    if let Some(line) = text.lines.last_mut() {
+                        if message.edited_at.is_some() {
+                            line.push_span(Span::raw(" (edited)").dim());
+                        }
+                        if message.author == chat.session.username {
+                            line.push_span(Span::raw(receipt_mark(chat, message)).dim());
+                        }
+                    }
+                    if !message.reactions.is_empty() {
+                        let reactions = message
+                            .reactions
+                            .iter()
+                            .map(|reaction| format!("{} {}", reaction.emoji, reaction.count))
+                            .collect::<Vec<_>>();
+                        text.push_line(Line::from(format!("  {}", reactions.join("  "))).dim());
+                    }
+                    for attachment in &message.attachments {
+                        text.push_line(
+                            Line::from(format!(
+                                "  📎 {} ({} bytes)",
+                                attachment.name, attachment.size
+                            ))
+                            .dim(),
+                        );
+                    }
+                    if let Some(reply_to) = message.reply_to {
+                        text.lines.insert(0, quote(messages.get(&reply_to), width));
+                    }
+                    ListItem::new(text)
+                })
+                .collect::<Vec<_>>();
+            (title, lines)
+        }
+        None => ("Pick a conversation".to_string(), Vec::new()),
+    };
+    // Selecting the newest visible message keeps the list scrolled to the bottom.
+    let newest = lines
+        .len()
+        .checked_sub(1)
+        .map(|last| last.saturating_sub(chat.scroll));
+    frame.render_stateful_widget(
+        List::new(lines).block(Block::bordered().title(format!(" {title} "))),
+        message_pane,
+        &mut ListState::default().with_selected(newest),
+    );
+
+    frame.render_widget(
+        Paragraph::new(chat.input.as_str()).block(Block::bordered().title(" Message ")),
+        input,
+    );
+    frame.set_cursor_position(cursor(input, &chat.input));
+}
+
+fn wrap<'a>(author: &'a str, content: &'a str, width: usize) -> Text<'a> {
+    let mut text = Text::default();
+    let mut line = Line::from(vec![Span::raw(author).bold(), Span::raw(": ")]);
+    let mut budget = width.saturating_sub(author.chars().count() + 2).max(1);
+    let mut rest = content;
+    loop {
+        let split = rest
+            .char_indices()
+            .nth(budget)
+            .map_or(rest.len(), |(index, _)| index);
+        let (chunk, tail) = rest.split_at(split);
+        line.push_span(Span::raw(chunk));
+        text.push_line(line);
+        if tail.is_empty() {
+            return text;
+        }
+        line = Line::default();
+        budget = width.max(1);
+        rest = tail;
+    }
+}
+
+fn receipt_mark(chat: &Chat, message: &Message) -> &'static str {
+    let Some(receipts) = chat.receipts.get(&message.id) else {
+        return "";
+    };
+    let all = |reached: fn(&Receipt) -> bool| {
+        message
+            .recipients
+            .iter()
+            .filter(|user| **user != chat.session.username)
+            .all(|user| receipts.get(user).is_some_and(reached))
+    };
+    if all(|receipt| receipt.read_at.is_some()) {
+        " ✓✓"
+    } else if all(|receipt| receipt.delivered_at.is_some()) {
+        " ✓"
+    } else {
+        ""
+    }
+}
+
+fn quote(parent: Option<&Message>, width: usize) -> Line<'static> {
+    let excerpt = match parent {
+        Some(parent) if parent.deleted => format!("{}: (deleted)", parent.author),
+        Some(parent) => format!("{}: {}", parent.author, parent.content),
+        None => "an earlier message".to_string(),
+    };
+    let excerpt = excerpt
+        .chars()
+        .map(|c| if c == '\n' { ' ' } else { c })
+        .take(width.saturating_sub(2))
+        .collect::<String>();
+    Line::from(format!("> {excerpt}")).dim()
+}
+
+fn cursor(area: Rect, value: &str) -> Position {
+    let offset = u16::try_from(value.chars().count()).unwrap_or(u16::MAX);
+    Position::new(
+        (area.x + 1 + offset).min(area.right().saturating_sub(2)),
+        area.y + 1,
+    )
+}
+
+fn focus_style(focused: bool) -> Style {
+    if focused {
+        Style::new().fg(Color::Cyan)
+    } else {
+        Style::new()
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use pigeon_protocol::{Channel, Message};
+
+/// Where a message belongs from one user's point of view: a channel, or everyone else on a
+/// direct message.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Conversation {
+    Channel(u64),
+    Direct(BTreeSet<String>),
+}
+
+impl Conversation {
+    pub fn of(message: &Message, me: &str) -> Self {
+        match message.channel {
+            Some(channel) => Self::Channel(channel),
+            None => Self::Direct(
+                message
+                    .recipients
+                    .iter()
+                    .chain([&message.author])
+                    .filter(|user| *user != me)
+                    .cloned()
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Falls back to the channel's id when it isn't in `channels`.
+    pub fn title(&self, channels: &BTreeMap<u64, Channel>) -> String {
+        match self {
+            Self::Channel(id) => channels
+                .get(id)
+                .map_or_else(|| format!("#{id}"), |channel| format!("#{}", channel.name)),
+            Self::Direct(users) if users.is_empty() => "Notes to self".to_string(),
+            Self::Direct(users) => users.iter().cloned().collect::<Vec<_>>().join(", "),
+        }
+    }
+}
+
+/// Parses a comma-separated list of usernames, skipping empty entries.
+pub fn split_users(users: &str) -> impl Iterator<Item = String> + '_ {
+    users
+        .split(',')
+        .map(str::trim)
+        .filter(|user| !user.is_empty())
+        .map(str::to_string)
+}
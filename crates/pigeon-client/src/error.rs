@@ -0,0 +1,65 @@
+use pigeon_protocol::{ErrorBody, ErrorKind};
+use thiserror::Error;
+use tokio_tungstenite::tungstenite::Error as WsError;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{body}")]
+    Api { status: u16, body: ErrorBody },
+    #[error("Server responded with status {0}!")]
+    Status(u16),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    WebSocket(Box<WsError>),
+    #[error("Not logged in!")]
+    NotLoggedIn,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<WsError> for Error {
+    fn from(err: WsError) -> Self {
+        Self::WebSocket(Box::new(err))
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> Option<&ErrorKind> {
+        match self {
+            Self::Api { body, .. } => Some(&body.kind),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Api { status, .. } | Self::Status(status) => Some(*status),
+            Self::Http(err) => err.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_transient(&self, idempotent: bool) -> bool {
+        match self {
+            Self::Http(err) => err.is_connect() || (idempotent && err.is_timeout()),
+            Self::WebSocket(_) => true,
+            // The server turns away 429s and 503s before doing anything, but a gateway error may
+            // come back after the request already went through.
+            _ => match self.status() {
+                Some(429 | 503) => true,
+                Some(502 | 504) => idempotent,
+                _ => false,
+            },
+        }
+    }
+
+    pub(crate) fn retry_after(&self) -> Option<u64> {
+        match self.kind() {
+            Some(ErrorKind::RateLimited { retry_after }) => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
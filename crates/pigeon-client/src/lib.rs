@@ -0,0 +1,405 @@
+pub mod conversation;
+mod error;
+mod retry;
+mod stream;
+
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use reqwest::{Method, RequestBuilder};
+use serde::de::DeserializeOwned;
+
+pub use error::{Error, Result};
+pub use pigeon_protocol::{
+    Attachment, Channel, Edit, ErrorBody, ErrorKind, Event, Message, Preferences, Reaction,
+    Receipt, SearchParams, SearchResults, Session, Thread,
+};
+pub use retry::RetryPolicy;
+pub use stream::EventStream;
+
+use pigeon_protocol::{
+    AckRequest, CreateChannelRequest, EditRequest, InviteRequest, LoginRequest, ReactionRequest,
+    RecvRequest, RegisterRequest, RenameChannelRequest, SendRequest, ThreadParams, UploadParams,
+};
+
+#[derive(Debug)]
+pub struct Client {
+    base: String,
+    http: reqwest::Client,
+    retry: RetryPolicy,
+    session: RwLock<Option<Session>>,
+}
+
+impl Client {
+    pub fn new(base: impl Into<String>) -> Self {
+        Self {
+            base: base.into().trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            retry: RetryPolicy::default(),
+            session: RwLock::new(None),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_session(self, session: Session) -> Self {
+        *self.session.write() = Some(session);
+        self
+    }
+
+    pub fn session(&self) -> Option<Session> {
+        self.session.read().clone()
+    }
+
+    pub async fn register(&self, username: &str, password: &str) -> Result<()> {
+        let request = RegisterRequest {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+        self.call(
+            self.request(Method::POST, "/register").json(&request),
+            false,
+        )
+        .await
+    }
+
+    pub async fn login(&self, username: &str, password: &str) -> Result<Session> {
+        let request = LoginRequest {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+        let session: Session = self
+            .call(self.request(Method::POST, "/login").json(&request), true)
+            .await?;
+        *self.session.write() = Some(session.clone());
+        Ok(session)
+    }
+
+    pub async fn logout(&self) -> Result<()> {
+        self.call::<()>(self.authorized(Method::POST, "/logout")?, false)
+            .await?;
+        *self.session.write() = None;
+        Ok(())
+    }
+
+    pub async fn refresh(&self) -> Result<Session> {
+        let session: Session = self
+            .call(self.authorized(Method::POST, "/refresh")?, false)
+            .await?;
+        *self.session.write() = Some(session.clone());
+        Ok(session)
+    }
+
+    pub async fn send(&self, mut message: Message) -> Result<Message> {
+        if message.author.is_empty() {
+            message.author = self.username()?;
+        }
+        let request = SendRequest {
+            password: None,
+            message,
+        };
+        self.call(
+            self.authorized(Method::POST, "/message")?.json(&request),
+            false,
+        )
+        .await
+    }
+
+    pub async fn send_to(&self, recipients: &[String], content: &str) -> Result<Message> {
+        self.send(Message {
+            author: self.username()?,
+            content: content.to_string(),
+            recipients: recipients.to_vec(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn send_to_channel(&self, channel: u64, content: &str) -> Result<Message> {
+        self.send(Message {
+            author: self.username()?,
+            content: content.to_string(),
+            channel: Some(channel),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Replies land in the parent's conversation, so no recipients are needed.
+    pub async fn reply(&self, parent: u64, content: &str) -> Result<Message> {
+        self.send(Message {
+            author: self.username()?,
+            content: content.to_string(),
+            reply_to: Some(parent),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Uploads are stored by hash, so sending the same file twice is safe to retry.
+    pub async fn upload(&self, name: &str, contents: Vec<u8>) -> Result<Attachment> {
+        let params = UploadParams {
+            name: name.to_string(),
+        };
+        self.call(
+            self.authorized(Method::POST, "/attachment")?
+                .query(&params)
+                .body(contents),
+            true,
+        )
+        .await
+    }
+
+    pub async fn download(&self, id: u64, hash: &str) -> Result<Vec<u8>> {
+        let path = format!("/message/{id}/attachment/{hash}");
+        self.call_raw(self.authorized(Method::GET, &path)?, true)
+            .await
+    }
+
+    pub async fn thread(&self, id: u64, after: u64, limit: Option<usize>) -> Result<Thread> {
+        let params = ThreadParams { after, limit };
+        let path = format!("/message/{id}/thread");
+        self.call(self.authorized(Method::GET, &path)?.query(&params), true)
+            .await
+    }
+
+    pub async fn search(&self, params: &SearchParams) -> Result<SearchResults> {
+        self.call(self.authorized(Method::GET, "/search")?.query(params), true)
+            .await
+    }
+
+    /// Every message the logged-in user wrote and hasn't deleted, newest first. The inbox only
+    /// holds what others sent them, so this is the other half of each conversation.
+    pub async fn authored(&self) -> Result<Vec<Message>> {
+        let author = self.session().ok_or(Error::NotLoggedIn)?.username;
+        let mut messages = Vec::new();
+        loop {
+            let params = SearchParams {
+                author: Some(author.clone()),
+                offset: messages.len(),
+                ..Default::default()
+            };
+            let page = self.search(&params).await?;
+            let done =
+                page.messages.is_empty() || messages.len() + page.messages.len() >= page.total;
+            messages.extend(page.messages.into_iter().map(Arc::unwrap_or_clone));
+            if done {
+                return Ok(messages);
+            }
+        }
+    }
+
+    /// Not retried after a gateway error, since an edit that went through would be recorded twice.
+    pub async fn edit(&self, id: u64, content: &str) -> Result<Message> {
+        let request = EditRequest {
+            content: content.to_string(),
+        };
+        let path = format!("/message/{id}");
+        self.call(self.authorized(Method::PATCH, &path)?.json(&request), false)
+            .await
+    }
+
+    pub async fn delete(&self, id: u64) -> Result<Message> {
+        let path = format!("/message/{id}");
+        self.call(self.authorized(Method::DELETE, &path)?, false)
+            .await
+    }
+
+    pub async fn react(&self, id: u64, emoji: &str) -> Result<Message> {
+        let request = ReactionRequest {
+            emoji: emoji.to_string(),
+        };
+        let path = format!("/message/{id}/reaction");
+        self.call(self.authorized(Method::PUT, &path)?.json(&request), true)
+            .await
+    }
+
+    pub async fn unreact(&self, id: u64, emoji: &str) -> Result<Message> {
+        let request = ReactionRequest {
+            emoji: emoji.to_string(),
+        };
+        let path = format!("/message/{id}/reaction");
+        self.call(self.authorized(Method::DELETE, &path)?.json(&request), true)
+            .await
+    }
+
+    /// Messages whose revision is past `after`, so edits and deletes show up again.
+    pub async fn recv(&self, after: u64, limit: Option<usize>) -> Result<Vec<Message>> {
+        let request = RecvRequest {
+            after,
+            limit,
+            ..Default::default()
+        };
+        self.call(
+            self.authorized(Method::GET, "/message")?.json(&request),
+            true,
+        )
+        .await
+    }
+
+    /// The newest revision in the inbox, for streaming only what arrives from now on.
+    pub async fn cursor(&self) -> Result<u64> {
+        self.call(self.authorized(Method::GET, "/cursor")?, true)
+            .await
+    }
+
+    /// Marks messages as read; authors only hear about it while read receipts are on.
+    pub async fn ack(&self, messages: &[u64]) -> Result<()> {
+        let request = AckRequest {
+            messages: messages.to_vec(),
+        };
+        self.call(self.authorized(Method::POST, "/ack")?.json(&request), true)
+            .await
+    }
+
+    pub async fn receipts(&self, id: u64) -> Result<Vec<Receipt>> {
+        let path = format!("/message/{id}/receipts");
+        self.call(self.authorized(Method::GET, &path)?, true).await
+    }
+
+    pub async fn preferences(&self) -> Result<Preferences> {
+        self.call(self.authorized(Method::GET, "/preferences")?, true)
+            .await
+    }
+
+    pub async fn set_preferences(&self, preferences: &Preferences) -> Result<Preferences> {
+        self.call(
+            self.authorized(Method::PUT, "/preferences")?
+                .json(preferences),
+            true,
+        )
+        .await
+    }
+
+    pub fn stream(&self, after: u64) -> Result<EventStream> {
+        let token = self.token()?;
+        let url = match self.base.strip_prefix("http") {
+            Some(rest) => format!("ws{rest}"),
+            None => self.base.clone(),
+        };
+        Ok(EventStream::spawn(url, token, after, self.retry))
+    }
+
+    pub async fn channels(&self) -> Result<Vec<Channel>> {
+        self.call(self.authorized(Method::GET, "/channel")?, true)
+            .await
+    }
+
+    pub async fn create_channel(&self, name: &str, members: &[String]) -> Result<Channel> {
+        let request = CreateChannelRequest {
+            name: name.to_string(),
+            members: members.to_vec(),
+        };
+        self.call(
+            self.authorized(Method::POST, "/channel")?.json(&request),
+            false,
+        )
+        .await
+    }
+
+    pub async fn rename_channel(&self, channel: u64, name: &str) -> Result<Channel> {
+        let request = RenameChannelRequest {
+            name: name.to_string(),
+        };
+        let path = format!("/channel/{channel}/rename");
+        self.call(self.authorized(Method::POST, &path)?.json(&request), true)
+            .await
+    }
+
+    pub async fn invite(&self, channel: u64, username: &str) -> Result<Channel> {
+        let request = InviteRequest {
+            username: username.to_string(),
+        };
+        let path = format!("/channel/{channel}/invite");
+        self.call(self.authorized(Method::POST, &path)?.json(&request), true)
+            .await
+    }
+
+    pub async fn leave_channel(&self, channel: u64) -> Result<Channel> {
+        let path = format!("/channel/{channel}/leave");
+        self.call(self.authorized(Method::POST, &path)?, false)
+            .await
+    }
+
+    fn username(&self) -> Result<String> {
+        self.session
+            .read()
+            .as_ref()
+            .map(|session| session.username.clone())
+            .ok_or(Error::NotLoggedIn)
+    }
+
+    fn token(&self) -> Result<String> {
+        self.session
+            .read()
+            .as_ref()
+            .map(|session| session.token.clone())
+            .ok_or(Error::NotLoggedIn)
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http.request(method, format!("{}{}", self.base, path))
+    }
+
+    fn authorized(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        Ok(self.request(method, path).bearer_auth(self.token()?))
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        idempotent: bool,
+    ) -> Result<T> {
+        let body = self.call_raw(request, idempotent).await?;
+        // Endpoints that succeed without a body still need to deserialize into `()`.
+        Ok(serde_json::from_slice(if body.is_empty() {
+            b"null"
+        } else {
+            &body
+        })?)
+    }
+
+    async fn call_raw(&self, request: RequestBuilder, idempotent: bool) -> Result<Vec<u8>> {
+        let mut attempt = 0;
+        loop {
+            let attempt_request = request
+                .try_clone()
+                .expect("request bodies are always buffered");
+            match execute(attempt_request).await {
+                Err(err) if attempt < self.retry.max_retries && err.is_transient(idempotent) => {
+                    let delay = match err.retry_after() {
+                        Some(secs) => std::time::Duration::from_secs(secs),
+                        None => self.retry.delay(attempt),
+                    };
+                    // Lockouts can last far longer than anyone wants a call to hang.
+                    if delay > self.retry.max_delay {
+                        return Err(err);
+                    }
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+async fn execute(request: RequestBuilder) -> Result<Vec<u8>> {
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.bytes().await?;
+
+    if !status.is_success() {
+        return Err(match serde_json::from_slice::<ErrorBody>(&body) {
+            Ok(body) => Error::Api {
+                status: status.as_u16(),
+                body,
+            },
+            Err(_) => Error::Status(status.as_u16()),
+        });
+    }
+    Ok(body.to_vec())
+}
@@ -0,0 +1,100 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt};
+use pigeon_protocol::{ErrorBody, ErrorKind, Event};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{
+    client::IntoClientRequest,
+    http::{header::AUTHORIZATION, HeaderValue},
+    Error as WsError, Message as WsMessage,
+};
+
+use crate::{Error, Result, RetryPolicy};
+
+pub struct EventStream {
+    events: mpsc::Receiver<Result<Event>>,
+}
+
+impl Stream for EventStream {
+    type Item = Result<Event>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+impl EventStream {
+    pub(crate) fn spawn(url: String, token: String, after: u64, retry: RetryPolicy) -> Self {
+        let (tx, events) = mpsc::channel(64);
+        tokio::spawn(follow(url, token, after, retry, tx));
+        Self { events }
+    }
+}
+
+async fn follow(
+    url: String,
+    token: String,
+    mut after: u64,
+    retry: RetryPolicy,
+    tx: mpsc::Sender<Result<Event>>,
+) {
+    let mut attempt = 0;
+    loop {
+        match connect(&url, &token, after).await {
+            Ok(mut socket) => {
+                attempt = 0;
+                while let Some(frame) = socket.next().await {
+                    let event = match frame {
+                        Ok(WsMessage::Text(text)) => serde_json::from_str::<Event>(&text),
+                        Ok(_) => continue,
+                        Err(_) => break,
+                    };
+                    if let Some(message) = event.as_ref().ok().and_then(Event::message) {
+                        after = after.max(message.revision);
+                    }
+                    if tx.send(event.map_err(Error::from)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(Error::WebSocket(err)) if matches!(&*err, WsError::Http(response) if response.status() == 401) =>
+            {
+                let _ = tx
+                    .send(Err(Error::Api {
+                        status: 401,
+                        body: ErrorKind::InvalidSession.into(),
+                    }))
+                    .await;
+                return;
+            }
+            Err(_) => attempt += 1,
+        }
+
+        if tx.is_closed() {
+            return;
+        }
+        tokio::time::sleep(retry.delay(attempt)).await;
+    }
+}
+
+async fn connect(
+    url: &str,
+    token: &str,
+    after: u64,
+) -> Result<
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
+> {
+    let mut request = format!("{url}/ws?after={after}").into_client_request()?;
+    request.headers_mut().insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {token}")).map_err(|_| Error::Api {
+            status: 401,
+            body: ErrorBody::from(ErrorKind::InvalidSession),
+        })?,
+    );
+    let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+    Ok(socket)
+}
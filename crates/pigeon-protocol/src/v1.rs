@@ -0,0 +1,385 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Message {
+    #[serde(default)]
+    pub id: u64,
+    #[serde(default)]
+    pub timestamp: u64,
+    pub author: String,
+    pub content: String,
+    pub recipients: Vec<String>,
+    #[serde(default)]
+    pub channel: Option<u64>,
+    /// Bumped on every edit and delete; `after` cursors count revisions rather than ids.
+    #[serde(default)]
+    pub revision: u64,
+    #[serde(default)]
+    pub edited_at: Option<u64>,
+    #[serde(default)]
+    pub history: Vec<Edit>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub reply_to: Option<u64>,
+    /// Id of the first message in the thread, filled in by the server for replies.
+    #[serde(default)]
+    pub thread: Option<u64>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+impl Message {
+    pub fn involves(&self, username: &str) -> bool {
+        self.author == username || self.recipients.iter().any(|user| user == username)
+    }
+}
+
+/// An uploaded file a message points to, only downloadable by that message's participants.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Attachment {
+    /// Hex SHA-256 of the contents, which is all a client needs to send when referencing it.
+    pub hash: String,
+    #[serde(default)]
+    pub name: String,
+    /// Sniffed from the contents by the server rather than taken from the uploader.
+    #[serde(default)]
+    pub mime: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+/// A superseded version of a message's content.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Edit {
+    pub content: String,
+    pub timestamp: u64,
+}
+
+/// Everyone who reacted to a message with the same emoji. Messages list these in the order each
+/// emoji was first used, while `users` is sorted by name.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Reaction {
+    pub emoji: String,
+    pub count: usize,
+    pub users: BTreeSet<String>,
+}
+
+/// How far a message got with one of its recipients, only shown to the author.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Receipt {
+    pub message: u64,
+    pub user: String,
+    #[serde(default)]
+    pub delivered_at: Option<u64>,
+    #[serde(default)]
+    pub read_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub read_receipts: bool,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            read_receipts: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Channel {
+    pub id: u64,
+    pub name: String,
+    pub members: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Session {
+    pub token: String,
+    pub username: String,
+    pub expires_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Message {
+        message: Arc<Message>,
+    },
+    Reply {
+        message: Arc<Message>,
+        parent: Arc<Message>,
+    },
+    Edit {
+        message: Arc<Message>,
+    },
+    Delete {
+        message: Arc<Message>,
+    },
+    Reaction {
+        message: Arc<Message>,
+        user: String,
+        emoji: String,
+        added: bool,
+    },
+    /// Every receipt one batch of deliveries or reads changed on `author`'s messages.
+    Receipts {
+        author: String,
+        receipts: Vec<Receipt>,
+    },
+    Channel {
+        channel: Channel,
+    },
+}
+
+impl Event {
+    pub fn message(&self) -> Option<&Arc<Message>> {
+        match self {
+            Self::Message { message }
+            | Self::Reply { message, .. }
+            | Self::Edit { message }
+            | Self::Delete { message }
+            | Self::Reaction { message, .. } => Some(message),
+            Self::Receipts { .. } | Self::Channel { .. } => None,
+        }
+    }
+
+    pub fn visible_to(&self, username: &str) -> bool {
+        match self {
+            Self::Channel { channel } => channel.members.contains(username),
+            // Authors aren't recipients of their own messages but still want to see reactions.
+            Self::Reaction { message, .. } => message.involves(username),
+            Self::Receipts { author, .. } => author == username,
+            _ => self
+                .message()
+                .is_some_and(|message| message.recipients.iter().any(|user| user == username)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RegisterRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SendRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub message: Message,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EditRequest {
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReactionRequest {
+    pub emoji: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AckRequest {
+    pub messages: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct RecvRequest {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub after: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct StreamParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub after: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct ThreadParams {
+    #[serde(default)]
+    pub after: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Thread {
+    pub root: Arc<Message>,
+    pub replies: Vec<Arc<Message>>,
+}
+
+/// Words in `query` must all appear, and anything in double quotes must appear as a phrase.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    pub query: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SearchResults {
+    /// Matches across all pages, not just this one.
+    pub total: usize,
+    pub messages: Vec<Arc<Message>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct UploadParams {
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CreateChannelRequest {
+    pub name: String,
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RenameChannelRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InviteRequest {
+    pub username: String,
+}
+
+pub type LoginResponse = Session;
+pub type RefreshResponse = Session;
+pub type SendResponse = Message;
+pub type EditResponse = Message;
+pub type DeleteResponse = Message;
+pub type ReactionResponse = Message;
+pub type RecvResponse = Vec<Message>;
+pub type ThreadResponse = Thread;
+pub type SearchResponse = SearchResults;
+pub type UploadResponse = Attachment;
+pub type ReceiptsResponse = Vec<Receipt>;
+pub type PreferencesResponse = Preferences;
+pub type ChannelResponse = Channel;
+pub type ChannelsResponse = Vec<Channel>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error, Deserialize, Serialize)]
+#[serde(tag = "code", content = "detail", rename_all = "snake_case")]
+pub enum ErrorKind {
+    #[error("Message author doesn't exist!")]
+    NonExistentMessageAuthor,
+    #[error("Session owner doesn't exist!")]
+    NonExistentSessionOwner,
+    #[error("Recipient {0} doesn't exist!")]
+    UnknownRecipient(String),
+    #[error("User {0} doesn't exist!")]
+    UnknownUser(String),
+    #[error("Message doesn't exist!")]
+    NonExistentMessage,
+    #[error("Only the author of this message can do that!")]
+    NotMessageAuthor,
+    #[error("Reaction {0} isn't allowed!")]
+    InvalidReaction(String),
+    #[error("Message already has {limit} different reactions!")]
+    TooManyReactions { limit: usize },
+    #[error("Attachment doesn't exist!")]
+    NonExistentAttachment,
+    #[error("Messages can't have more than {limit} attachments!")]
+    TooManyAttachments { limit: usize },
+    #[error("Username is already taken!")]
+    UsernameTaken,
+    #[error("Wrong username or password!")]
+    BadCredentials,
+    #[error("Session is invalid or has expired!")]
+    InvalidSession,
+    #[error("Channel doesn't exist!")]
+    NonExistentChannel,
+    #[error("User isn't a member of this channel!")]
+    NotChannelMember,
+    #[error("Payload is larger than {limit} bytes!")]
+    PayloadTooLarge { limit: usize },
+    #[error("Too many requests, retry in {retry_after} seconds!")]
+    RateLimited { retry_after: u64 },
+    #[error("Server is busy, try again later!")]
+    Overloaded,
+    /// The request couldn't be parsed, with what was wrong with it.
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Internal server error!")]
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::NonExistentMessageAuthor
+            | Self::UnknownRecipient(_)
+            | Self::UnknownUser(_)
+            | Self::InvalidReaction(_) => 406,
+            Self::UsernameTaken => 409,
+            Self::NonExistentSessionOwner | Self::BadCredentials | Self::InvalidSession => 401,
+            Self::NonExistentChannel => 404,
+            Self::NotChannelMember => 403,
+            Self::NonExistentMessage => 404,
+            Self::NotMessageAuthor => 403,
+            Self::TooManyReactions { .. } => 409,
+            Self::NonExistentAttachment => 404,
+            Self::TooManyAttachments { .. } => 413,
+            Self::PayloadTooLarge { .. } => 413,
+            Self::RateLimited { .. } => 429,
+            Self::Overloaded => 503,
+            Self::InvalidRequest(_) => 400,
+            Self::Internal => 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error, Deserialize, Serialize)]
+#[error("{message}")]
+pub struct ErrorBody {
+    #[serde(flatten)]
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl From<ErrorKind> for ErrorBody {
+    fn from(kind: ErrorKind) -> Self {
+        Self {
+            message: kind.to_string(),
+            kind,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::{fmt::Debug, sync::Arc};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+
+use pigeon_protocol::*;
+
+fn round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: T) {
+    let json = serde_json::to_string(&value).unwrap();
+    assert_eq!(serde_json::from_str::<T>(&json).unwrap(), value, "{json}");
+}
+
+fn message() -> Message {
+    Message {
+        id: 7,
+        timestamp: 1_666_000_000,
+        author: "alice".to_string(),
+        content: "hello".to_string(),
+        recipients: vec!["bob".to_string(), "carol".to_string()],
+        channel: Some(3),
+        revision: 9,
+        edited_at: Some(1_666_000_100),
+        history: vec![Edit {
+            content: "helo".to_string(),
+            timestamp: 1_666_000_000,
+        }],
+        deleted: false,
+        reply_to: Some(5),
+        thread: Some(2),
+        reactions: vec![Reaction {
+            emoji: "👍".to_string(),
+            count: 2,
+            users: ["bob", "carol"].map(String::from).into(),
+        }],
+        attachments: vec![Attachment {
+            hash: "ab".repeat(32),
+            name: "build.log".to_string(),
+            mime: "text/plain; charset=utf-8".to_string(),
+            size: 2048,
+        }],
+    }
+}
+
+fn channel() -> Channel {
+    Channel {
+        id: 3,
+        name: "general".to_string(),
+        members: ["alice", "bob"].map(String::from).into(),
+    }
+}
+
+#[test]
+fn domain_types() {
+    round_trip(message());
+    round_trip(channel());
+    round_trip(Session {
+        token: "abc".to_string(),
+        username: "alice".to_string(),
+        expires_at: 42,
+    });
+    round_trip(Event::Message {
+        message: Arc::new(message()),
+    });
+    round_trip(Event::Reply {
+        message: Arc::new(message()),
+        parent: Arc::new(Message {
+            id: 5,
+            reply_to: None,
+            thread: None,
+            ..message()
+        }),
+    });
+    round_trip(Event::Edit {
+        message: Arc::new(message()),
+    });
+    round_trip(Event::Delete {
+        message: Arc::new(Message {
+            content: String::new(),
+            history: Vec::new(),
+            deleted: true,
+            ..message()
+        }),
+    });
+    round_trip(Event::Reaction {
+        message: Arc::new(message()),
+        user: "bob".to_string(),
+        emoji: "👍".to_string(),
+        added: true,
+    });
+    round_trip(Event::Receipts {
+        author: "alice".to_string(),
+        receipts: vec![Receipt {
+            message: 7,
+            user: "bob".to_string(),
+            delivered_at: Some(1_666_000_050),
+            read_at: None,
+        }],
+    });
+    round_trip(Event::Channel { channel: channel() });
+    round_trip(Thread {
+        root: Arc::new(message()),
+        replies: vec![Arc::new(message())],
+    });
+    round_trip(SearchResults {
+        total: 3,
+        messages: vec![Arc::new(message())],
+    });
+}
+
+#[test]
+fn requests() {
+    round_trip(RegisterRequest {
+        username: "alice".to_string(),
+        password: "hunter2".to_string(),
+    });
+    round_trip(LoginRequest {
+        username: "alice".to_string(),
+        password: "hunter2".to_string(),
+    });
+    round_trip(SendRequest {
+        password: None,
+        message: message(),
+    });
+    round_trip(SendRequest {
+        password: Some("hunter2".to_string()),
+        message: message(),
+    });
+    round_trip(EditRequest {
+        content: "hello again".to_string(),
+    });
+    round_trip(ReactionRequest {
+        emoji: "🎉".to_string(),
+    });
+    round_trip(AckRequest {
+        messages: vec![7, 9],
+    });
+    round_trip(Preferences::default());
+    round_trip(Preferences {
+        read_receipts: false,
+    });
+    round_trip(RecvRequest::default());
+    round_trip(RecvRequest {
+        username: "bob".to_string(),
+        password: Some("hunter2".to_string()),
+        after: 12,
+        limit: Some(50),
+    });
+    round_trip(StreamParams {
+        token: Some("abc".to_string()),
+        after: 12,
+    });
+    round_trip(ThreadParams::default());
+    round_trip(ThreadParams {
+        after: 3,
+        limit: Some(20),
+    });
+    round_trip(SearchParams::default());
+    round_trip(SearchParams {
+        query: "\"ship it\" friday".to_string(),
+        author: Some("bob".to_string()),
+        since: Some(1_700_000_000),
+        until: Some(1_702_592_000),
+        offset: 20,
+        limit: Some(10),
+    });
+    round_trip(UploadParams {
+        name: "screenshot.png".to_string(),
+    });
+    round_trip(CreateChannelRequest {
+        name: "general".to_string(),
+        members: vec!["bob".to_string()],
+    });
+    round_trip(RenameChannelRequest {
+        name: "random".to_string(),
+    });
+    round_trip(InviteRequest {
+        username: "carol".to_string(),
+    });
+}
+
+#[test]
+fn errors() {
+    for kind in [
+        ErrorKind::NonExistentMessageAuthor,
+        ErrorKind::NonExistentSessionOwner,
+        ErrorKind::UnknownRecipient("bob".to_string()),
+        ErrorKind::UnknownUser("bob".to_string()),
+        ErrorKind::UsernameTaken,
+        ErrorKind::BadCredentials,
+        ErrorKind::InvalidSession,
+        ErrorKind::NonExistentChannel,
+        ErrorKind::NotChannelMember,
+        ErrorKind::NonExistentMessage,
+        ErrorKind::NotMessageAuthor,
+        ErrorKind::InvalidReaction("x y".to_string()),
+        ErrorKind::TooManyReactions { limit: 20 },
+        ErrorKind::NonExistentAttachment,
+        ErrorKind::TooManyAttachments { limit: 10 },
+        ErrorKind::PayloadTooLarge { limit: 16 },
+        ErrorKind::RateLimited { retry_after: 5 },
+        ErrorKind::Overloaded,
+        ErrorKind::Internal,
+    ] {
+        round_trip(ErrorBody::from(kind));
+    }
+}
+
+#[test]
+fn error_wire_format() {
+    assert_eq!(
+        serde_json::to_value(ErrorBody::from(ErrorKind::UnknownRecipient(
+            "bob".to_string()
+        )))
+        .unwrap(),
+        json!({
+            "code": "unknown_recipient",
+            "detail": "bob",
+            "message": "Recipient bob doesn't exist!",
+        })
+    );
+    assert_eq!(
+        serde_json::to_value(ErrorBody::from(ErrorKind::UsernameTaken)).unwrap(),
+        json!({ "code": "username_taken", "message": "Username is already taken!" })
+    );
+}
+
+#[test]
+fn message_without_server_fields() {
+    let message: Message = serde_json::from_value(json!({
+        "author": "alice",
+        "content": "hello",
+        "recipients": ["bob"],
+    }))
+    .unwrap();
+    assert_eq!(
+        (
+            message.id,
+            message.timestamp,
+            message.channel,
+            message.revision,
+            message.deleted,
+            message.reply_to,
+        ),
+        (0, 0, None, 0, false, None)
+    );
+}
+
+#[test]
+fn preferences_default_to_sending_receipts() {
+    let preferences: Preferences = serde_json::from_value(json!({})).unwrap();
+    assert!(preferences.read_receipts);
+}
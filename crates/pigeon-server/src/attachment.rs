@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use axum::{
+    body::HttpBody,
+    extract::{Path, Query, RawBody},
+    headers::{authorization::Bearer, Authorization},
+    http::header,
+    response::IntoResponse,
+    Extension, Json, TypedHeader,
+};
+use parking_lot::RwLock;
+
+use pigeon_protocol::UploadParams;
+use pigeon_server::{blob::BlobStore, AppError, Attachment, ErrorKind, State};
+
+use crate::{eyre::Report, session_user};
+
+pub async fn upload(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Query(params): Query<UploadParams>,
+    RawBody(mut body): RawBody,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+    Extension(blobs): Extension<Arc<BlobStore>>,
+) -> Result<Json<Attachment>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    // Read in chunks so an oversized upload is cut off instead of buffered whole.
+    let mut contents = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(Report::from)?;
+        if contents.len() + chunk.len() > blobs.max_size() {
+            return Err(ErrorKind::PayloadTooLarge {
+                limit: blobs.max_size(),
+            }
+            .into());
+        }
+        contents.extend_from_slice(&chunk);
+    }
+
+    Ok(Json(blobs.put(&username, &params.name, contents).await?))
+}
+
+pub async fn download(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path((id, hash)): Path<(u64, String)>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+    Extension(blobs): Extension<Arc<BlobStore>>,
+) -> Result<impl IntoResponse, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    let attachment = state.read().attachment(id, &username, &hash)?;
+    let contents = blobs.get(&attachment.hash).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.mime),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", attachment.name),
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        contents,
+    ))
+}
@@ -0,0 +1,177 @@
+use std::{io::ErrorKind as IoErrorKind, path::PathBuf};
+
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncReadExt};
+
+use crate::{
+    eyre::{ensure, Result},
+    Attachment, ErrorKind,
+};
+
+pub const MAX_NAME_LEN: usize = 255;
+const SNIFF_LEN: usize = 512;
+
+/// Attachment contents on disk, named by their SHA-256 so identical uploads share one file.
+/// Who uploaded each one is kept alongside, as empty files under `uploaders/<hash>/`.
+#[derive(Debug)]
+pub struct BlobStore {
+    dir: PathBuf,
+    max_size: usize,
+}
+
+impl BlobStore {
+    pub fn new(dir: impl Into<PathBuf>, max_size: usize) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, max_size })
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub async fn put(&self, uploader: &str, name: &str, contents: Vec<u8>) -> Result<Attachment> {
+        ensure!(
+            contents.len() <= self.max_size,
+            ErrorKind::PayloadTooLarge {
+                limit: self.max_size
+            }
+        );
+        let (hash, contents) = tokio::task::spawn_blocking(move || {
+            let hash = Sha256::digest(&contents)
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>();
+            (hash, contents)
+        })
+        .await?;
+        let path = self.path(&hash)?;
+        if !fs::try_exists(&path).await? {
+            // Written aside and renamed so a crash never leaves a truncated blob under its hash.
+            let partial = self
+                .dir
+                .join(format!(".{hash}.{:016x}", rand::random::<u64>()));
+            fs::write(&partial, &contents).await?;
+            fs::rename(&partial, &path).await?;
+        }
+        let uploaders = self.uploaders(&hash);
+        fs::create_dir_all(&uploaders).await?;
+        fs::write(uploaders.join(hex(uploader)), []).await?;
+
+        Ok(Attachment {
+            hash,
+            name: clean_name(name),
+            mime: sniff(&contents[..contents.len().min(SNIFF_LEN)]).to_string(),
+            size: contents.len() as u64,
+        })
+    }
+
+    pub async fn get(&self, hash: &str) -> Result<Vec<u8>> {
+        match fs::read(self.path(hash)?).await {
+            Err(err) if err.kind() == IoErrorKind::NotFound => {
+                Err(ErrorKind::NonExistentAttachment.into())
+            }
+            result => Ok(result?),
+        }
+    }
+
+    pub async fn uploaded_by(&self, hash: &str, username: &str) -> Result<bool> {
+        self.path(hash)?;
+        Ok(fs::try_exists(self.uploaders(hash).join(hex(username))).await?)
+    }
+
+    /// Checks a client-supplied attachment was uploaded, filling in its size and type from disk.
+    pub async fn resolve(&self, attachment: &Attachment) -> Result<Attachment> {
+        let mut file = match fs::File::open(self.path(&attachment.hash)?).await {
+            Err(err) if err.kind() == IoErrorKind::NotFound => {
+                return Err(ErrorKind::NonExistentAttachment.into())
+            }
+            result => result?,
+        };
+        let size = file.metadata().await?.len();
+        let mut head = Vec::with_capacity(SNIFF_LEN);
+        (&mut file)
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut head)
+            .await?;
+
+        Ok(Attachment {
+            hash: attachment.hash.clone(),
+            name: clean_name(&attachment.name),
+            mime: sniff(&head).to_string(),
+            size,
+        })
+    }
+
+    fn path(&self, hash: &str) -> Result<PathBuf> {
+        ensure!(
+            hash.len() == 64
+                && hash
+                    .bytes()
+                    .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f')),
+            ErrorKind::NonExistentAttachment
+        );
+        Ok(self.dir.join(hash))
+    }
+
+    fn uploaders(&self, hash: &str) -> PathBuf {
+        self.dir.join("uploaders").join(hash)
+    }
+}
+
+/// Usernames can hold anything, so they're hex-encoded before being used as file names.
+fn hex(username: &str) -> String {
+    username.bytes().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Keeps the last path component and drops anything that could break a `Content-Disposition`.
+pub fn clean_name(name: &str) -> String {
+    let name = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .collect::<String>();
+    let mut end = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    match name[..end].trim() {
+        "" | "." | ".." => "attachment".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Guesses a type from magic numbers, since whatever the uploader claims can't be trusted.
+pub fn sniff(head: &[u8]) -> &'static str {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+    ];
+
+    if let Some((_, mime)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+    {
+        return mime;
+    }
+    if head.len() >= 12 && head.starts_with(b"RIFF") && &head[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    // The head may stop partway through a character, which still counts as text.
+    let text = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    };
+    if text && !head.contains(&0) {
+        "text/plain; charset=utf-8"
+    } else {
+        "application/octet-stream"
+    }
+}
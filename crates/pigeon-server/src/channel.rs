@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Path,
+    headers::{authorization::Bearer, Authorization},
+    Extension, Json, TypedHeader,
+};
+use parking_lot::RwLock;
+
+use pigeon_protocol::{CreateChannelRequest, InviteRequest, RenameChannelRequest};
+use pigeon_server::{AppError, Channel, State};
+
+use crate::session_user;
+
+pub async fn create(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Json(create_info): Json<CreateChannelRequest>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Channel>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.write().create_channel(
+        &username,
+        create_info.name,
+        create_info.members,
+    )?))
+}
+
+pub async fn list(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Vec<Channel>>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.read().channels_of(&username)))
+}
+
+pub async fn rename(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<u64>,
+    Json(rename_info): Json<RenameChannelRequest>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Channel>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.write().rename_channel(
+        id,
+        &username,
+        rename_info.name,
+    )?))
+}
+
+pub async fn invite(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<u64>,
+    Json(invite_info): Json<InviteRequest>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Channel>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.write().invite_to_channel(
+        id,
+        &username,
+        &invite_info.username,
+    )?))
+}
+
+pub async fn leave(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<u64>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Channel>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.write().leave_channel(id, &username)?))
+}
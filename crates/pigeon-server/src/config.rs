@@ -0,0 +1,419 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::eyre::{bail, ensure, Result, WrapErr};
+use pigeon_server::{limit::Limits, password::HashScheme};
+
+const DEFAULT_CONFIG_FILE: &str = "pigeon.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Pretty,
+    Compact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Bcrypt,
+    Argon2id,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: SocketAddr,
+    pub log_format: LogFormat,
+    pub recv_limit: usize,
+    pub compact_interval_secs: u64,
+    pub shutdown_timeout_secs: u64,
+    pub storage: StorageConfig,
+    pub attachments: AttachmentConfig,
+    pub password: PasswordConfig,
+    pub limits: Limits,
+    pub tls: TlsConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: Backend,
+    pub users_file: PathBuf,
+    pub messages_file: PathBuf,
+    pub sessions_file: PathBuf,
+    pub channels_file: PathBuf,
+    pub receipts_file: PathBuf,
+    pub preferences_file: PathBuf,
+    pub log_file: PathBuf,
+    pub sqlite_file: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttachmentConfig {
+    pub dir: PathBuf,
+    pub max_size: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordConfig {
+    pub algorithm: HashAlgorithm,
+    pub bcrypt_cost: u32,
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub hash_workers: usize,
+    pub hash_queue: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub redirect_bind: Option<SocketAddr>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: ([0, 0, 0, 0], 3000).into(),
+            log_format: if cfg!(debug_assertions) {
+                LogFormat::Pretty
+            } else {
+                LogFormat::Compact
+            },
+            recv_limit: 500,
+            compact_interval_secs: 5 * 60,
+            shutdown_timeout_secs: 30,
+            storage: StorageConfig::default(),
+            attachments: AttachmentConfig::default(),
+            password: PasswordConfig::default(),
+            limits: Limits::default(),
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Json,
+            users_file: "users.json".into(),
+            messages_file: "messages.json".into(),
+            sessions_file: "sessions.json".into(),
+            channels_file: "channels.json".into(),
+            receipts_file: "receipts.json".into(),
+            preferences_file: "preferences.json".into(),
+            log_file: "pigeon.wal".into(),
+            sqlite_file: "pigeon.db".into(),
+        }
+    }
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        Self {
+            dir: "attachments".into(),
+            max_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: HashAlgorithm::Argon2id,
+            bcrypt_cost: 12,
+            argon2_memory_kib: 19 * 1024,
+            argon2_iterations: 2,
+            argon2_parallelism: 1,
+            hash_workers: std::thread::available_parallelism().map_or(1, usize::from),
+            hash_queue: 64,
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "pigeon-server", version, about = "The pigeon chat server")]
+pub struct Args {
+    #[arg(
+        short,
+        long,
+        env = "PIGEON_CONFIG",
+        help = "Config file [default: pigeon.toml if present]"
+    )]
+    pub config: Option<PathBuf>,
+    #[arg(long, help = "Print the effective configuration and exit")]
+    pub print_config: bool,
+    #[arg(long, help = "Validate the configuration and exit")]
+    pub check: bool,
+
+    #[arg(long, env = "PIGEON_BIND")]
+    bind: Option<SocketAddr>,
+    #[arg(long, env = "PIGEON_LOG_FORMAT")]
+    log_format: Option<LogFormat>,
+    #[arg(long, env = "PIGEON_RECV_LIMIT")]
+    recv_limit: Option<usize>,
+    #[arg(long, env = "PIGEON_COMPACT_INTERVAL_SECS")]
+    compact_interval_secs: Option<u64>,
+    #[arg(long, env = "PIGEON_SHUTDOWN_TIMEOUT_SECS")]
+    shutdown_timeout_secs: Option<u64>,
+
+    #[arg(long, env = "PIGEON_STORAGE")]
+    storage: Option<Backend>,
+    #[arg(long, env = "PIGEON_USERS_FILE")]
+    users_file: Option<PathBuf>,
+    #[arg(long, env = "PIGEON_MESSAGES_FILE")]
+    messages_file: Option<PathBuf>,
+    #[arg(long, env = "PIGEON_SESSIONS_FILE")]
+    sessions_file: Option<PathBuf>,
+    #[arg(long, env = "PIGEON_CHANNELS_FILE")]
+    channels_file: Option<PathBuf>,
+    #[arg(long, env = "PIGEON_RECEIPTS_FILE")]
+    receipts_file: Option<PathBuf>,
+    #[arg(long, env = "PIGEON_PREFERENCES_FILE")]
+    preferences_file: Option<PathBuf>,
+    #[arg(long, env = "PIGEON_LOG_FILE")]
+    log_file: Option<PathBuf>,
+    #[arg(long, env = "PIGEON_SQLITE_FILE")]
+    sqlite_file: Option<PathBuf>,
+
+    #[arg(long, env = "PIGEON_ATTACHMENTS_DIR")]
+    attachments_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "PIGEON_ATTACHMENT_MAX_SIZE",
+        help = "Largest upload accepted, in bytes"
+    )]
+    attachment_max_size: Option<usize>,
+
+    #[arg(long, env = "PIGEON_PASSWORD_ALGORITHM")]
+    password_algorithm: Option<HashAlgorithm>,
+    #[arg(long, env = "PIGEON_BCRYPT_COST")]
+    bcrypt_cost: Option<u32>,
+    #[arg(long, env = "PIGEON_ARGON2_MEMORY_KIB")]
+    argon2_memory_kib: Option<u32>,
+    #[arg(long, env = "PIGEON_ARGON2_ITERATIONS")]
+    argon2_iterations: Option<u32>,
+    #[arg(long, env = "PIGEON_ARGON2_PARALLELISM")]
+    argon2_parallelism: Option<u32>,
+    #[arg(
+        long,
+        env = "PIGEON_HASH_WORKERS",
+        help = "Threads reserved for password hashing"
+    )]
+    hash_workers: Option<usize>,
+    #[arg(
+        long,
+        env = "PIGEON_HASH_QUEUE",
+        help = "Hashes allowed to wait before returning 503"
+    )]
+    hash_queue: Option<usize>,
+
+    #[arg(
+        long,
+        env = "PIGEON_LIMIT_IP_PER_MINUTE",
+        help = "Credential checks per IP, 0 disables"
+    )]
+    limit_ip_per_minute: Option<u32>,
+    #[arg(long, env = "PIGEON_LIMIT_IP_BURST")]
+    limit_ip_burst: Option<u32>,
+    #[arg(
+        long,
+        env = "PIGEON_LIMIT_USER_PER_MINUTE",
+        help = "Login attempts per username, 0 disables"
+    )]
+    limit_user_per_minute: Option<u32>,
+    #[arg(long, env = "PIGEON_LIMIT_USER_BURST")]
+    limit_user_burst: Option<u32>,
+    #[arg(
+        long,
+        env = "PIGEON_LOCKOUT_AFTER",
+        help = "Failed logins before lockout, 0 disables"
+    )]
+    lockout_after: Option<u32>,
+    #[arg(long, env = "PIGEON_LOCKOUT_BASE_SECS")]
+    lockout_base_secs: Option<u64>,
+    #[arg(long, env = "PIGEON_LOCKOUT_MAX_SECS")]
+    lockout_max_secs: Option<u64>,
+
+    #[arg(
+        long,
+        env = "PIGEON_TLS_CERT",
+        help = "PEM certificate chain, enables TLS"
+    )]
+    tls_cert: Option<PathBuf>,
+    #[arg(long, env = "PIGEON_TLS_KEY", help = "PEM private key for --tls-cert")]
+    tls_key: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "PIGEON_REDIRECT_BIND",
+        help = "Plain HTTP address redirecting to HTTPS"
+    )]
+    redirect_bind: Option<SocketAddr>,
+}
+
+impl Config {
+    /// Layers the config file, then `PIGEON_*` variables, then flags; clap already resolves the
+    /// last two in that order.
+    pub fn load(args: &Args) -> Result<Self> {
+        let mut config = match &args.config {
+            Some(path) => read(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                read(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+
+        macro_rules! layer {
+            ($($field:ident => $target:expr),* $(,)?) => {
+                $(if let Some(value) = args.$field.clone() {
+                    $target = value;
+                })*
+            };
+        }
+        layer! {
+            bind => config.bind,
+            log_format => config.log_format,
+            recv_limit => config.recv_limit,
+            compact_interval_secs => config.compact_interval_secs,
+            shutdown_timeout_secs => config.shutdown_timeout_secs,
+            storage => config.storage.backend,
+            users_file => config.storage.users_file,
+            messages_file => config.storage.messages_file,
+            sessions_file => config.storage.sessions_file,
+            channels_file => config.storage.channels_file,
+            receipts_file => config.storage.receipts_file,
+            preferences_file => config.storage.preferences_file,
+            log_file => config.storage.log_file,
+            sqlite_file => config.storage.sqlite_file,
+            attachments_dir => config.attachments.dir,
+            attachment_max_size => config.attachments.max_size,
+            password_algorithm => config.password.algorithm,
+            bcrypt_cost => config.password.bcrypt_cost,
+            argon2_memory_kib => config.password.argon2_memory_kib,
+            argon2_iterations => config.password.argon2_iterations,
+            argon2_parallelism => config.password.argon2_parallelism,
+            hash_workers => config.password.hash_workers,
+            hash_queue => config.password.hash_queue,
+            limit_ip_per_minute => config.limits.ip_per_minute,
+            limit_ip_burst => config.limits.ip_burst,
+            limit_user_per_minute => config.limits.user_per_minute,
+            limit_user_burst => config.limits.user_burst,
+            lockout_after => config.limits.lockout_after,
+            lockout_base_secs => config.limits.lockout_base_secs,
+            lockout_max_secs => config.limits.lockout_max_secs,
+        }
+        if args.tls_cert.is_some() {
+            config.tls.cert = args.tls_cert.clone();
+        }
+        if args.tls_key.is_some() {
+            config.tls.key = args.tls_key.clone();
+        }
+        if args.redirect_bind.is_some() {
+            config.tls.redirect_bind = args.redirect_bind;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(self.recv_limit > 0, "recv_limit must be at least 1");
+        ensure!(
+            self.compact_interval_secs > 0,
+            "compact_interval_secs must be at least 1"
+        );
+        ensure!(
+            self.attachments.max_size > 0,
+            "attachments.max_size must be at least 1"
+        );
+        ensure!(
+            self.password.hash_workers > 0,
+            "password.hash_workers must be at least 1"
+        );
+        ensure!(
+            (4..=31).contains(&self.password.bcrypt_cost),
+            "bcrypt_cost must be between 4 and 31, got {}",
+            self.password.bcrypt_cost
+        );
+        if let Err(err) = argon2::Params::new(
+            self.password.argon2_memory_kib,
+            self.password.argon2_iterations,
+            self.password.argon2_parallelism,
+            None,
+        ) {
+            bail!("Invalid Argon2 parameters: {}", err);
+        }
+
+        ensure!(
+            self.limits.lockout_base_secs <= self.limits.lockout_max_secs,
+            "limits.lockout_base_secs must not exceed limits.lockout_max_secs"
+        );
+
+        match (&self.tls.cert, &self.tls.key) {
+            (Some(cert), Some(key)) => {
+                for path in [cert, key] {
+                    ensure!(path.is_file(), "{} is not a readable file", path.display());
+                }
+            }
+            (None, None) => ensure!(
+                self.tls.redirect_bind.is_none(),
+                "tls.redirect_bind needs tls.cert and tls.key"
+            ),
+            _ => bail!("tls.cert and tls.key must be set together"),
+        }
+        if let Some(redirect_bind) = self.tls.redirect_bind {
+            ensure!(
+                redirect_bind != self.bind,
+                "tls.redirect_bind must differ from bind"
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn compact_interval(&self) -> Duration {
+        Duration::from_secs(self.compact_interval_secs)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+
+    pub fn password_hash(&self) -> HashScheme {
+        match self.password.algorithm {
+            HashAlgorithm::Bcrypt => HashScheme::Bcrypt {
+                cost: self.password.bcrypt_cost,
+            },
+            HashAlgorithm::Argon2id => HashScheme::Argon2id {
+                memory_kib: self.password.argon2_memory_kib,
+                iterations: self.password.argon2_iterations,
+                parallelism: self.password.argon2_parallelism,
+            },
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Config> {
+    let contents =
+        fs::read_to_string(path).wrap_err_with(|| format!("Error reading {}", path.display()))?;
+    toml::from_str(&contents).wrap_err_with(|| format!("Error parsing {}", path.display()))
+}
@@ -0,0 +1,85 @@
+use axum::{
+    body::HttpBody,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use pigeon_protocol::{ErrorBody, ErrorKind};
+
+use crate::eyre::Report;
+
+/// How much of a request axum's extractors buffer before rejecting it, which is what bounds
+/// every `Json` body.
+pub const BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct AppError(pub ErrorKind);
+
+impl From<ErrorKind> for AppError {
+    fn from(kind: ErrorKind) -> Self {
+        Self(kind)
+    }
+}
+
+impl From<Report> for AppError {
+    fn from(report: Report) -> Self {
+        Self(report.downcast().unwrap_or_else(|report| {
+            tracing::error!("{:?}", report);
+            ErrorKind::Internal
+        }))
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status =
+            StatusCode::from_u16(self.0.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let retry_after = match self.0 {
+            ErrorKind::RateLimited { retry_after } => Some(retry_after),
+            ErrorKind::Overloaded => Some(1),
+            _ => None,
+        };
+        let body = Json(ErrorBody::from(self.0));
+
+        match retry_after {
+            Some(retry_after) => (
+                status,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                body,
+            )
+                .into_response(),
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+/// Rewrites the plain-text rejections from axum's extractors and router into an `ErrorBody`,
+/// keeping their status, so clients get a machine-readable code for every failure.
+pub async fn json_errors<B>(request: Request<B>, next: Next<B>) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if is_json || !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+
+    let mut body = response.into_body();
+    let mut text = Vec::new();
+    while let Some(Ok(chunk)) = body.data().await {
+        text.extend_from_slice(&chunk);
+    }
+    let kind = match status {
+        StatusCode::PAYLOAD_TOO_LARGE => ErrorKind::PayloadTooLarge { limit: BODY_LIMIT },
+        status if status.is_server_error() => ErrorKind::Internal,
+        status => ErrorKind::InvalidRequest(match String::from_utf8_lossy(&text).trim() {
+            "" => status.canonical_reason().unwrap_or_default().to_string(),
+            text => text.to_string(),
+        }),
+    };
+
+    (status, Json(ErrorBody::from(kind))).into_response()
+}
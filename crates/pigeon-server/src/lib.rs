@@ -1,48 +1,911 @@
+pub mod blob;
+mod error;
 mod eyre;
+pub mod limit;
+pub mod password;
+pub mod search;
+pub mod storage;
+
+pub use error::{json_errors, AppError};
+pub use pigeon_protocol::{
+    Attachment, Channel, Edit, ErrorKind, Event, Message, Preferences, Reaction, Receipt,
+    SearchParams, SearchResults, Session, Thread,
+};
 
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Bound,
+    sync::Arc,
     time::UNIX_EPOCH,
 };
 
-use eyre::{ensure, Result};
-use serde::{Deserialize, Serialize};
-use thiserror::Error;
+use eyre::{bail, ensure, Result};
+use parking_lot::RwLock;
+use password::HashPool;
+use rand::Rng;
+use search::{Index, Query};
+use storage::{Dump, Snapshot, Storage};
+use tokio::sync::broadcast;
+
+pub const SESSION_TTL_SECS: u64 = 60 * 60 * 24;
+pub const EVENT_CAPACITY: usize = 1024;
+pub const MAX_CONTENT_LEN: usize = 16 * 1024;
+pub const MAX_REACTIONS: usize = 20;
+pub const MAX_REACTION_LEN: usize = 64;
+pub const MAX_ATTACHMENTS: usize = 10;
 
 #[derive(Debug)]
 pub struct State {
     pub users: HashMap<String, String>,
-    pub messages: BTreeMap<u64, Vec<Message>>,
+    pub messages: BTreeMap<u64, Arc<Message>>,
+    pub inboxes: HashMap<String, BTreeMap<u64, Arc<Message>>>,
+    pub threads: HashMap<u64, BTreeSet<u64>>,
+    pub sessions: HashMap<String, Session>,
+    pub channels: BTreeMap<u64, Channel>,
+    pub receipts: BTreeMap<u64, BTreeMap<String, Receipt>>,
+    pub preferences: HashMap<String, Preferences>,
+    index: Index,
+    next_sequence: u64,
+    storage: Box<dyn Storage>,
+    events: broadcast::Sender<Event>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Message {
-    pub author: String,
-    pub content: String,
-    pub recipients: Vec<String>,
-}
+impl State {
+    pub fn load(mut storage: Box<dyn Storage>) -> Result<Self> {
+        let Snapshot {
+            users,
+            messages,
+            sessions,
+            channels,
+            receipts,
+            preferences,
+        } = storage.load()?;
+        let mut state = Self {
+            users,
+            next_sequence: messages
+                .values()
+                .map(|message| message.id.max(message.revision))
+                .max()
+                .map_or(1, |last| last + 1),
+            messages: BTreeMap::new(),
+            inboxes: HashMap::new(),
+            threads: HashMap::new(),
+            sessions,
+            channels,
+            receipts,
+            preferences,
+            index: Index::default(),
+            storage,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        };
+        for mut message in messages.into_values() {
+            // Messages stored before edits existed have never been revised.
+            message.revision = message.revision.max(message.id);
+            state.index_message(Arc::new(message));
+        }
+        Ok(state)
+    }
 
-#[derive(Debug, Error, Deserialize, Serialize)]
-pub enum AppError {
-    #[error("Message author doesn't exist!")]
-    NonExistentMessageAuthor,
-}
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
 
-impl State {
-    pub fn add_message_at_present(&mut self, message: Message) -> Result<()> {
-        let timestamp = UNIX_EPOCH.elapsed()?.as_secs();
+    /// Starts compacting storage if it needs it, returning the part to run without the state
+    /// locked.
+    pub fn start_compaction(&mut self) -> Result<Option<impl FnOnce() -> Result<()> + Send>> {
+        let compaction = match self.storage.start_compaction()? {
+            Some(compaction) => compaction,
+            None => return Ok(None),
+        };
+        let dump = Dump {
+            users: self.users.clone(),
+            messages: self.messages.clone(),
+            sessions: self.sessions.clone(),
+            channels: self.channels.clone(),
+            receipts: self.receipts.clone(),
+            preferences: self.preferences.clone(),
+        };
+        Ok(Some(move || compaction(&dump)))
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(compaction) = self.start_compaction()? {
+            compaction()?;
+        }
+        self.storage.flush()
+    }
+
+    pub fn add_user(&mut self, username: String, hash: String) -> Result<()> {
+        ensure!(
+            !self.users.contains_key(&username),
+            ErrorKind::UsernameTaken
+        );
+        self.set_user(username, hash)
+    }
+
+    pub fn set_user(&mut self, username: String, hash: String) -> Result<()> {
+        self.storage.put_user(&username, &hash)?;
+        self.users.insert(username, hash);
+        Ok(())
+    }
+
+    pub fn add_message_at_present(&mut self, mut message: Message) -> Result<Arc<Message>> {
         ensure!(
             self.users.contains_key(&message.author),
-            AppError::NonExistentMessageAuthor
+            ErrorKind::NonExistentMessageAuthor
         );
-        self.messages
-            .entry(timestamp)
-            .and_modify(|messages| messages.push(message.clone()))
-            .or_insert_with(|| vec![message]);
+        ensure!(
+            message.content.len() <= MAX_CONTENT_LEN,
+            ErrorKind::PayloadTooLarge {
+                limit: MAX_CONTENT_LEN
+            }
+        );
+        ensure!(
+            message.attachments.len() <= MAX_ATTACHMENTS,
+            ErrorKind::TooManyAttachments {
+                limit: MAX_ATTACHMENTS
+            }
+        );
+        let parent = match message.reply_to {
+            Some(id) => {
+                let parent = Arc::clone(self.visible_message(id, &message.author)?);
+                ensure!(!parent.deleted, ErrorKind::NonExistentMessage);
+                // Replies stay in the conversation they started in.
+                message.channel = parent.channel;
+                message.recipients = parent
+                    .recipients
+                    .iter()
+                    .chain([&parent.author])
+                    .filter(|user| **user != message.author)
+                    .cloned()
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect();
+                if message.recipients.is_empty() {
+                    message.recipients.push(message.author.clone());
+                }
+                message.thread = Some(parent.thread.unwrap_or(parent.id));
+                Some(parent)
+            }
+            None => {
+                message.thread = None;
+                None
+            }
+        };
+        if let Some(recipient) = message
+            .recipients
+            .iter()
+            .find(|recipient| !self.users.contains_key(*recipient))
+        {
+            bail!(ErrorKind::UnknownRecipient(recipient.clone()));
+        }
+        if let Some(channel) = message.channel {
+            message.recipients = self
+                .member_channel(channel, &message.author)?
+                .members
+                .iter()
+                .cloned()
+                .collect();
+        }
+        message.id = self.next_sequence;
+        message.timestamp = UNIX_EPOCH.elapsed()?.as_secs();
+        // Only `edit_message` and `delete_message` get to mark a message as changed.
+        message.edited_at = None;
+        message.history.clear();
+        message.deleted = false;
+        message.reactions.clear();
+        self.put_message(message, |message| match parent {
+            Some(parent) => Event::Reply { message, parent },
+            None => Event::Message { message },
+        })
+    }
+
+    pub fn edit_message(
+        &mut self,
+        id: u64,
+        username: &str,
+        content: String,
+    ) -> Result<Arc<Message>> {
+        ensure!(
+            content.len() <= MAX_CONTENT_LEN,
+            ErrorKind::PayloadTooLarge {
+                limit: MAX_CONTENT_LEN
+            }
+        );
+        let mut message = Message::clone(self.authored_message(id, username)?);
+        let previous = Edit {
+            content: std::mem::replace(&mut message.content, content),
+            timestamp: message.edited_at.unwrap_or(message.timestamp),
+        };
+        message.history.push(previous);
+        message.edited_at = Some(UNIX_EPOCH.elapsed()?.as_secs());
+        self.put_message(message, |message| Event::Edit { message })
+    }
+
+    pub fn delete_message(&mut self, id: u64, username: &str) -> Result<Arc<Message>> {
+        let mut message = Message::clone(self.authored_message(id, username)?);
+        message.content.clear();
+        message.history.clear();
+        message.reactions.clear();
+        message.attachments.clear();
+        message.deleted = true;
+        message.edited_at = Some(UNIX_EPOCH.elapsed()?.as_secs());
+        self.put_message(message, |message| Event::Delete { message })
+    }
+
+    pub fn add_reaction(&mut self, id: u64, username: &str, emoji: String) -> Result<Arc<Message>> {
+        self.set_reaction(id, username, emoji, true)
+    }
+
+    pub fn remove_reaction(
+        &mut self,
+        id: u64,
+        username: &str,
+        emoji: String,
+    ) -> Result<Arc<Message>> {
+        self.set_reaction(id, username, emoji, false)
+    }
+
+    pub fn inbox(&self, username: &str, after: u64, limit: usize) -> Vec<Arc<Message>> {
+        self.inboxes.get(username).map_or_else(Vec::new, |inbox| {
+            inbox
+                .range((Bound::Excluded(after), Bound::Unbounded))
+                .map(|(_, message)| Arc::clone(message))
+                .take(limit)
+                .collect()
+        })
+    }
+
+    /// The revision of the newest message in `username`'s inbox, or 0 if it's empty.
+    pub fn latest_revision(&self, username: &str) -> u64 {
+        self.inboxes
+            .get(username)
+            .and_then(|inbox| inbox.keys().next_back().copied())
+            .unwrap_or(0)
+    }
+
+    pub fn thread(&self, id: u64, username: &str, after: u64, limit: usize) -> Result<Thread> {
+        let message = self.visible_message(id, username)?;
+        let root = Arc::clone(self.visible_message(message.thread.unwrap_or(id), username)?);
+        let replies = self.threads.get(&root.id).map_or_else(Vec::new, |replies| {
+            replies
+                .range((Bound::Excluded(after), Bound::Unbounded))
+                .filter_map(|id| self.messages.get(id))
+                .filter(|reply| reply.involves(username))
+                .map(Arc::clone)
+                .take(limit)
+                .collect()
+        });
+        Ok(Thread { root, replies })
+    }
+
+    /// Whether `hash` is attached to a message `username` can see, which lets them forward it.
+    pub fn can_see_attachment(&self, username: &str, hash: &str) -> bool {
+        self.messages.values().any(|message| {
+            message.involves(username)
+                && message
+                    .attachments
+                    .iter()
+                    .any(|attachment| attachment.hash == hash)
+        })
+    }
+
+    /// Looks up an attachment on a message `username` can see, so only participants download it.
+    pub fn attachment(&self, id: u64, username: &str, hash: &str) -> Result<Attachment> {
+        let attachment = self
+            .visible_message(id, username)?
+            .attachments
+            .iter()
+            .find(|attachment| attachment.hash == hash)
+            .ok_or(ErrorKind::NonExistentAttachment)?;
+        Ok(attachment.clone())
+    }
+
+    /// Ranks `username`'s messages against `params`, or lists them newest first without a query.
+    pub fn search(&self, username: &str, params: &SearchParams, limit: usize) -> SearchResults {
+        let query = Query::parse(&params.query);
+        let matches = |message: &Message| {
+            !message.deleted
+                && message.involves(username)
+                && params
+                    .author
+                    .as_ref()
+                    .is_none_or(|author| message.author == *author)
+                && params.since.is_none_or(|since| message.timestamp >= since)
+                && params.until.is_none_or(|until| message.timestamp <= until)
+                && query.matches_phrases(&message.content)
+        };
+
+        let mut results = if query.is_empty() {
+            self.messages
+                .values()
+                .rev()
+                .filter(|message| matches(message))
+                .map(|message| (Arc::clone(message), 0.0))
+                .collect::<Vec<_>>()
+        } else {
+            self.index
+                .search(&query)
+                .into_iter()
+                .filter_map(|(id, score)| Some((Arc::clone(self.messages.get(&id)?), score)))
+                .filter(|(message, _)| matches(message))
+                .collect()
+        };
+        results
+            .sort_by(|(a, a_score), (b, b_score)| b_score.total_cmp(a_score).then(b.id.cmp(&a.id)));
+
+        SearchResults {
+            total: results.len(),
+            messages: results
+                .into_iter()
+                .skip(params.offset)
+                .take(limit)
+                .map(|(message, _)| message)
+                .collect(),
+        }
+    }
+
+    /// Picks the event a stream replays `message` as, leaving out parents `username` can't see.
+    pub fn replay_event(&self, message: Arc<Message>, username: &str) -> Event {
+        if message.deleted {
+            return Event::Delete { message };
+        }
+        if message.edited_at.is_some() {
+            return Event::Edit { message };
+        }
+        match message
+            .reply_to
+            .and_then(|id| self.messages.get(&id))
+            .filter(|parent| parent.involves(username))
+        {
+            Some(parent) => Event::Reply {
+                message,
+                parent: Arc::clone(parent),
+            },
+            None => Event::Message { message },
+        }
+    }
+
+    /// Records that `username` has been handed `messages`, the first time it happens.
+    pub fn mark_delivered(&mut self, username: &str, messages: &[Arc<Message>]) -> Result<()> {
+        let now = UNIX_EPOCH.elapsed()?.as_secs();
+        let updates = messages
+            .iter()
+            .filter(|message| {
+                message.author != username && message.recipients.iter().any(|user| user == username)
+            })
+            .filter_map(|message| {
+                let mut receipt = self.receipt(message.id, username);
+                if receipt.delivered_at.is_some() {
+                    return None;
+                }
+                receipt.delivered_at = Some(now);
+                Some((message.author.clone(), receipt))
+            })
+            .collect();
+        self.store_receipts(updates)
+    }
+
+    /// Acknowledges `ids` as read, which only reaches the authors if `username` sends read receipts.
+    pub fn mark_read(&mut self, username: &str, ids: &[u64]) -> Result<()> {
+        let messages = ids
+            .iter()
+            .map(|id| self.visible_message(*id, username).map(Arc::clone))
+            .collect::<Result<Vec<_>>>()?;
+        let read_receipts = self.preferences(username).read_receipts;
+        let now = UNIX_EPOCH.elapsed()?.as_secs();
+        let updates = messages
+            .iter()
+            .filter(|message| message.author != username)
+            .filter_map(|message| {
+                let mut receipt = self.receipt(message.id, username);
+                let unchanged = receipt.clone();
+                receipt.delivered_at.get_or_insert(now);
+                if read_receipts {
+                    receipt.read_at.get_or_insert(now);
+                }
+                (receipt != unchanged).then(|| (message.author.clone(), receipt))
+            })
+            .collect();
+        self.store_receipts(updates)
+    }
+
+    pub fn receipts(&self, id: u64, username: &str) -> Result<Vec<Receipt>> {
+        let message = self.authored_message(id, username)?;
+        Ok(message
+            .recipients
+            .iter()
+            .filter(|user| *user != username)
+            .map(|user| self.receipt(id, user))
+            .collect())
+    }
+
+    pub fn preferences(&self, username: &str) -> Preferences {
+        self.preferences.get(username).cloned().unwrap_or_default()
+    }
+
+    pub fn set_preferences(
+        &mut self,
+        username: &str,
+        preferences: Preferences,
+    ) -> Result<Preferences> {
+        self.storage.put_preferences(username, &preferences)?;
+        self.preferences
+            .insert(username.to_string(), preferences.clone());
+        Ok(preferences)
+    }
+
+    pub fn channels_of(&self, username: &str) -> Vec<Channel> {
+        self.channels
+            .values()
+            .filter(|channel| channel.members.contains(username))
+            .cloned()
+            .collect()
+    }
+
+    pub fn create_channel(
+        &mut self,
+        creator: &str,
+        name: String,
+        members: impl IntoIterator<Item = String>,
+    ) -> Result<Channel> {
+        let mut members = members.into_iter().collect::<BTreeSet<_>>();
+        members.insert(creator.to_string());
+        if let Some(member) = members
+            .iter()
+            .find(|member| !self.users.contains_key(*member))
+        {
+            bail!(ErrorKind::UnknownUser(member.clone()));
+        }
+
+        let channel = Channel {
+            id: self.channels.keys().next_back().map_or(1, |id| id + 1),
+            name,
+            members,
+        };
+        self.put_channel(channel)
+    }
+
+    pub fn rename_channel(&mut self, id: u64, username: &str, name: String) -> Result<Channel> {
+        let mut channel = self.member_channel(id, username)?.clone();
+        channel.name = name;
+        self.put_channel(channel)
+    }
+
+    pub fn invite_to_channel(&mut self, id: u64, username: &str, invitee: &str) -> Result<Channel> {
+        ensure!(
+            self.users.contains_key(invitee),
+            ErrorKind::UnknownUser(invitee.to_string())
+        );
+        let mut channel = self.member_channel(id, username)?.clone();
+        channel.members.insert(invitee.to_string());
+        self.put_channel(channel)
+    }
+
+    pub fn leave_channel(&mut self, id: u64, username: &str) -> Result<Channel> {
+        let mut channel = self.member_channel(id, username)?.clone();
+        channel.members.remove(username);
+        self.put_channel(channel)
+    }
+
+    fn member_channel(&self, id: u64, username: &str) -> Result<&Channel> {
+        let channel = self
+            .channels
+            .get(&id)
+            .ok_or(ErrorKind::NonExistentChannel)?;
+        ensure!(
+            channel.members.contains(username),
+            ErrorKind::NotChannelMember
+        );
+        Ok(channel)
+    }
+
+    fn put_channel(&mut self, channel: Channel) -> Result<Channel> {
+        self.storage.put_channel(&channel)?;
+        self.channels.insert(channel.id, channel.clone());
+        let _ = self.events.send(Event::Channel {
+            channel: channel.clone(),
+        });
+        Ok(channel)
+    }
+
+    fn authored_message(&self, id: u64, username: &str) -> Result<&Arc<Message>> {
+        let message = self
+            .messages
+            .get(&id)
+            .filter(|message| !message.deleted)
+            .ok_or(ErrorKind::NonExistentMessage)?;
+        ensure!(message.author == username, ErrorKind::NotMessageAuthor);
+        Ok(message)
+    }
+
+    fn set_reaction(
+        &mut self,
+        id: u64,
+        username: &str,
+        emoji: String,
+        added: bool,
+    ) -> Result<Arc<Message>> {
+        ensure!(
+            !emoji.is_empty()
+                && emoji.len() <= MAX_REACTION_LEN
+                && !emoji.chars().any(|c| c.is_whitespace() || c.is_control()),
+            ErrorKind::InvalidReaction(emoji)
+        );
+        let current = Arc::clone(self.visible_message(id, username)?);
+        ensure!(!current.deleted, ErrorKind::NonExistentMessage);
+
+        let mut message = Message::clone(&current);
+        let index = message
+            .reactions
+            .iter()
+            .position(|reaction| reaction.emoji == emoji);
+        let changed = match (index, added) {
+            (Some(index), true) => message.reactions[index].users.insert(username.to_string()),
+            (None, true) => {
+                ensure!(
+                    message.reactions.len() < MAX_REACTIONS,
+                    ErrorKind::TooManyReactions {
+                        limit: MAX_REACTIONS
+                    }
+                );
+                message.reactions.push(Reaction {
+                    emoji: emoji.clone(),
+                    count: 0,
+                    users: BTreeSet::from([username.to_string()]),
+                });
+                true
+            }
+            (Some(index), false) => {
+                let removed = message.reactions[index].users.remove(username);
+                if message.reactions[index].users.is_empty() {
+                    message.reactions.remove(index);
+                }
+                removed
+            }
+            (None, false) => false,
+        };
+        if !changed {
+            return Ok(current);
+        }
+        for reaction in &mut message.reactions {
+            reaction.count = reaction.users.len();
+        }
+
+        let user = username.to_string();
+        self.put_message(message, move |message| Event::Reaction {
+            message,
+            user,
+            emoji,
+            added,
+        })
+    }
+
+    fn receipt(&self, id: u64, username: &str) -> Receipt {
+        self.receipts
+            .get(&id)
+            .and_then(|receipts| receipts.get(username))
+            .cloned()
+            .unwrap_or_else(|| pending_receipt(id, username))
+    }
+
+    /// Persists a batch of `(author, receipt)` updates in one write and tells each author once.
+    fn store_receipts(&mut self, updates: Vec<(String, Receipt)>) -> Result<()> {
+        if updates.is_empty() {
+            return Ok(());
+        }
+        let receipts = updates
+            .iter()
+            .map(|(_, receipt)| receipt.clone())
+            .collect::<Vec<_>>();
+        self.storage.put_receipts(&receipts)?;
+
+        let mut by_author = BTreeMap::<String, Vec<Receipt>>::new();
+        for (author, receipt) in updates {
+            self.receipts
+                .entry(receipt.message)
+                .or_default()
+                .insert(receipt.user.clone(), receipt.clone());
+            by_author.entry(author).or_default().push(receipt);
+        }
+        for (author, receipts) in by_author {
+            let _ = self.events.send(Event::Receipts { author, receipts });
+        }
         Ok(())
     }
+
+    fn visible_message(&self, id: u64, username: &str) -> Result<&Arc<Message>> {
+        let message = self
+            .messages
+            .get(&id)
+            .filter(|message| message.involves(username))
+            .ok_or(ErrorKind::NonExistentMessage)?;
+        Ok(message)
+    }
+
+    fn put_message(
+        &mut self,
+        mut message: Message,
+        event: impl FnOnce(Arc<Message>) -> Event,
+    ) -> Result<Arc<Message>> {
+        message.revision = self.next_sequence;
+        self.storage.put_message(&message)?;
+        self.next_sequence += 1;
+
+        let message = Arc::new(message);
+        self.index_message(Arc::clone(&message));
+        let _ = self.events.send(event(Arc::clone(&message)));
+        Ok(message)
+    }
+
+    fn index_message(&mut self, message: Arc<Message>) {
+        if let Some(thread) = message.thread {
+            self.threads.entry(thread).or_default().insert(message.id);
+        }
+        if let Some(previous) = self.messages.insert(message.id, Arc::clone(&message)) {
+            self.unindex_recipients(&previous);
+            self.index.remove(previous.id, &previous.content);
+        }
+        self.index.insert(message.id, &message.content);
+        for recipient in &message.recipients {
+            self.inboxes
+                .entry(recipient.clone())
+                .or_default()
+                .insert(message.revision, Arc::clone(&message));
+        }
+    }
+
+    fn unindex_recipients(&mut self, message: &Message) {
+        for recipient in &message.recipients {
+            if let Some(inbox) = self.inboxes.get_mut(recipient) {
+                inbox.remove(&message.revision);
+            }
+        }
+    }
+
+    pub fn create_session(&mut self, username: &str) -> Result<Session> {
+        ensure!(
+            self.users.contains_key(username),
+            ErrorKind::NonExistentSessionOwner
+        );
+        let now = UNIX_EPOCH.elapsed()?.as_secs();
+        let expired = self
+            .sessions
+            .values()
+            .filter(|session| session.expires_at <= now)
+            .map(|session| session.token.clone())
+            .collect::<Vec<_>>();
+        for token in expired {
+            self.revoke_session(&token)?;
+        }
+
+        let token = rand::thread_rng()
+            .gen::<[u8; 32]>()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        let session = Session {
+            token: token.clone(),
+            username: username.to_string(),
+            expires_at: now + SESSION_TTL_SECS,
+        };
+        self.storage.put_session(&session)?;
+        self.sessions.insert(token, session.clone());
+        Ok(session)
+    }
+
+    pub fn session_user(&self, token: &str) -> Result<Option<&str>> {
+        let now = UNIX_EPOCH.elapsed()?.as_secs();
+        Ok(self
+            .sessions
+            .get(token)
+            .filter(|session| session.expires_at > now)
+            .map(|session| session.username.as_str()))
+    }
+
+    pub fn refresh_session(&mut self, token: &str) -> Result<Option<Session>> {
+        let username = match self.session_user(token)? {
+            Some(username) => username.to_string(),
+            None => return Ok(None),
+        };
+        self.revoke_session(token)?;
+        self.create_session(&username).map(Some)
+    }
+
+    pub fn revoke_session(&mut self, token: &str) -> Result<bool> {
+        if self.sessions.remove(token).is_none() {
+            return Ok(false);
+        }
+        self.storage.remove_session(token)?;
+        Ok(true)
+    }
 }
 
-pub fn auth(state: &State, username: &str, password: &str) -> Result<bool> {
-    Ok(state.users.contains_key(username) && bcrypt::verify(password, &state.users[username])?)
+fn pending_receipt(message: u64, user: &str) -> Receipt {
+    Receipt {
+        message,
+        user: user.to_string(),
+        delivered_at: None,
+        read_at: None,
+    }
+}
+
+/// Checks `password` on the hashing pool; the state lock is only held to look up the hash.
+pub async fn auth(
+    state: &RwLock<State>,
+    pool: &HashPool,
+    username: &str,
+    password: &str,
+) -> Result<bool> {
+    let hash = match state.read().users.get(username) {
+        Some(hash) => hash.clone(),
+        None => return Ok(false),
+    };
+    pool.verify(password.to_string(), hash).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::SqliteStorage;
+
+    fn state() -> State {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        let mut state = State::load(Box::new(storage)).unwrap();
+        for user in ["alice", "bob", "carol"] {
+            state.add_user(user.to_string(), String::new()).unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn new_messages_cant_carry_reactions() {
+        let mut state = state();
+        let message = state
+            .add_message_at_present(Message {
+                author: "alice".to_string(),
+                content: "hello".to_string(),
+                recipients: vec!["bob".to_string()],
+                reactions: vec![Reaction {
+                    emoji: "👍".to_string(),
+                    count: 9999,
+                    users: ["bob", "carol"].map(String::from).into(),
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(message.reactions.is_empty());
+
+        let message = state
+            .add_reaction(message.id, "bob", "👍".to_string())
+            .unwrap();
+        assert_eq!(message.reactions.len(), 1);
+        assert_eq!(message.reactions[0].count, 1);
+        assert_eq!(message.reactions[0].users, ["bob"].map(String::from).into());
+    }
+
+    #[test]
+    fn inbox_holds_only_the_latest_revision_of_each_message() {
+        let mut state = state();
+        let [first, second] = ["first", "second"].map(|content| {
+            state
+                .add_message_at_present(Message {
+                    author: "alice".to_string(),
+                    content: content.to_string(),
+                    recipients: vec!["bob".to_string()],
+                    ..Default::default()
+                })
+                .unwrap()
+        });
+        let ids = |inbox: &[Arc<Message>]| inbox.iter().map(|m| m.id).collect::<Vec<_>>();
+
+        let cursor = state.latest_revision("bob");
+        let edited = state
+            .edit_message(first.id, "alice", "edited".to_string())
+            .unwrap();
+        assert_eq!(ids(&state.inbox("bob", cursor, usize::MAX)), [first.id]);
+        let inbox = state.inbox("bob", 0, usize::MAX);
+        assert_eq!(ids(&inbox), [second.id, first.id]);
+        assert_eq!(inbox[1].revision, edited.revision);
+        assert_eq!(inbox[1].content, "edited");
+
+        let cursor = state.latest_revision("bob");
+        let reacted = state
+            .add_reaction(second.id, "bob", "👍".to_string())
+            .unwrap();
+        assert_eq!(ids(&state.inbox("bob", cursor, usize::MAX)), [second.id]);
+        let inbox = state.inbox("bob", 0, usize::MAX);
+        assert_eq!(ids(&inbox), [first.id, second.id]);
+        assert_eq!(inbox[1].revision, reacted.revision);
+        assert_eq!(inbox[1].reactions.len(), 1);
+
+        let cursor = state.latest_revision("bob");
+        let deleted = state.delete_message(first.id, "alice").unwrap();
+        assert_eq!(ids(&state.inbox("bob", cursor, usize::MAX)), [first.id]);
+        let inbox = state.inbox("bob", 0, usize::MAX);
+        assert_eq!(ids(&inbox), [second.id, first.id]);
+        assert_eq!(inbox[1].revision, deleted.revision);
+        assert!(inbox[1].deleted);
+        assert_eq!(state.latest_revision("bob"), deleted.revision);
+    }
+
+    #[test]
+    fn inbox_cursor_can_be_past_everything() {
+        let mut state = state();
+        state
+            .add_message_at_present(Message {
+                author: "alice".to_string(),
+                content: "hello".to_string(),
+                recipients: vec!["bob".to_string()],
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(state.inbox("bob", 0, usize::MAX).len(), 1);
+        assert!(state.inbox("bob", u64::MAX, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn thread_cursor_can_be_past_everything() {
+        let mut state = state();
+        let root = state
+            .add_message_at_present(Message {
+                author: "alice".to_string(),
+                content: "hello".to_string(),
+                recipients: vec!["bob".to_string()],
+                ..Default::default()
+            })
+            .unwrap();
+        state
+            .add_message_at_present(Message {
+                author: "bob".to_string(),
+                content: "hi".to_string(),
+                reply_to: Some(root.id),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(
+            state
+                .thread(root.id, "bob", 0, usize::MAX)
+                .unwrap()
+                .replies
+                .len(),
+            1
+        );
+        assert!(state
+            .thread(root.id, "bob", u64::MAX, usize::MAX)
+            .unwrap()
+            .replies
+            .is_empty());
+    }
+
+    #[test]
+    fn deliveries_are_reported_once_per_author() {
+        let mut state = state();
+        let messages = (0..3)
+            .map(|_| {
+                state
+                    .add_message_at_present(Message {
+                        author: "alice".to_string(),
+                        content: "hello".to_string(),
+                        recipients: vec!["bob".to_string()],
+                        ..Default::default()
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut events = state.subscribe();
+
+        state.mark_delivered("bob", &messages).unwrap();
+        state.mark_delivered("bob", &messages).unwrap();
+        match events.try_recv().unwrap() {
+            Event::Receipts { author, receipts } => {
+                assert_eq!(author, "alice");
+                assert_eq!(receipts.len(), 3);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert!(events.try_recv().is_err());
+    }
 }
@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::ErrorKind;
+
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Token-bucket rates for credential checks. A `*_per_minute` of zero disables that bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub ip_per_minute: u32,
+    pub ip_burst: u32,
+    pub user_per_minute: u32,
+    pub user_burst: u32,
+    /// Failed attempts allowed before an account is locked out.
+    pub lockout_after: u32,
+    /// The first lockout lasts this long, and each further failure doubles it.
+    pub lockout_base_secs: u64,
+    pub lockout_max_secs: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            ip_per_minute: 30,
+            ip_burst: 10,
+            user_per_minute: 10,
+            user_burst: 5,
+            lockout_after: 5,
+            lockout_base_secs: 30,
+            lockout_max_secs: 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    ips: HashMap<IpAddr, Bucket>,
+    users: HashMap<String, Bucket>,
+    failures: HashMap<String, Failures>,
+}
+
+#[derive(Debug)]
+pub struct Limiter {
+    limits: Limits,
+    buckets: Mutex<Buckets>,
+}
+
+impl Limiter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// Spends a token for `ip` and, if given, `username`, failing while either is exhausted or
+    /// the account is locked out.
+    pub fn check(&self, ip: IpAddr, username: Option<&str>) -> Result<(), ErrorKind> {
+        let now = Instant::now();
+        let limits = &self.limits;
+        let mut buckets = self.buckets.lock();
+        let Buckets {
+            ips,
+            users,
+            failures,
+        } = &mut *buckets;
+
+        if let Some(locked_until) = username
+            .and_then(|username| failures.get(username))
+            .and_then(|failures| failures.locked_until)
+            .filter(|locked_until| *locked_until > now)
+        {
+            return Err(rate_limited(locked_until - now));
+        }
+
+        let ip_wait = wait(ips, ip, limits.ip_per_minute, limits.ip_burst, now);
+        let user_wait = username.and_then(|username| {
+            wait(
+                users,
+                username.to_string(),
+                limits.user_per_minute,
+                limits.user_burst,
+                now,
+            )
+        });
+        if let Some(delay) = ip_wait.max(user_wait) {
+            return Err(rate_limited(delay));
+        }
+
+        Ok(())
+    }
+
+    pub fn failed(&self, username: &str) {
+        let now = Instant::now();
+        let limits = &self.limits;
+        let mut buckets = self.buckets.lock();
+        let forgiven = Duration::from_secs(limits.lockout_max_secs);
+        if buckets.failures.len() >= PRUNE_THRESHOLD {
+            buckets
+                .failures
+                .retain(|_, failures| now.duration_since(failures.last) <= forgiven);
+        }
+        let failures = buckets
+            .failures
+            .entry(username.to_string())
+            .or_insert(Failures {
+                count: 0,
+                last: now,
+                locked_until: None,
+            });
+
+        // Old failures are forgiven once they're further apart than the longest lockout.
+        if now.duration_since(failures.last) > forgiven {
+            failures.count = 0;
+        }
+        failures.count += 1;
+        failures.last = now;
+
+        if limits.lockout_after > 0 && failures.count >= limits.lockout_after {
+            let doublings = (failures.count - limits.lockout_after).min(32);
+            let secs = limits
+                .lockout_base_secs
+                .saturating_mul(1 << doublings)
+                .min(limits.lockout_max_secs);
+            failures.locked_until = Some(now + Duration::from_secs(secs));
+            tracing::warn!(
+                "Locking {} out for {}s after {} failed attempts",
+                username,
+                secs,
+                failures.count
+            );
+        }
+    }
+
+    pub fn succeeded(&self, username: &str) {
+        self.buckets.lock().failures.remove(username);
+    }
+}
+
+/// Takes a token from `key`'s bucket, returning how long to wait if there isn't one.
+fn wait<K: Eq + Hash>(
+    buckets: &mut HashMap<K, Bucket>,
+    key: K,
+    per_minute: u32,
+    burst: u32,
+    now: Instant,
+) -> Option<Duration> {
+    if per_minute == 0 {
+        return None;
+    }
+    let rate = f64::from(per_minute) / 60.0;
+    let capacity = f64::from(burst.max(1));
+
+    if buckets.len() >= PRUNE_THRESHOLD {
+        buckets.retain(|_, bucket| {
+            bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < capacity
+        });
+    }
+
+    let bucket = buckets.entry(key).or_insert(Bucket {
+        tokens: capacity,
+        updated: now,
+    });
+    bucket.tokens =
+        (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(capacity);
+    bucket.updated = now;
+
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        None
+    } else {
+        Some(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+    }
+}
+
+fn rate_limited(delay: Duration) -> ErrorKind {
+    ErrorKind::RateLimited {
+        retry_after: delay.as_secs() + u64::from(delay.subsec_nanos() > 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lockout(limiter: &Limiter, username: &str) -> Option<u64> {
+        let buckets = limiter.buckets.lock();
+        let failures = &buckets.failures[username];
+        failures
+            .locked_until
+            .map(|locked_until| (locked_until - failures.last).as_secs())
+    }
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let mut buckets = HashMap::new();
+        let start = Instant::now();
+
+        // 60 a minute is one a second, on top of a burst of 2.
+        assert_eq!(wait(&mut buckets, "a", 60, 2, start), None);
+        assert_eq!(wait(&mut buckets, "a", 60, 2, start), None);
+        assert_eq!(
+            wait(&mut buckets, "a", 60, 2, start),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(wait(&mut buckets, "b", 60, 2, start), None);
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(wait(&mut buckets, "a", 60, 2, later), None);
+        assert!(wait(&mut buckets, "a", 60, 2, later).is_some());
+
+        // Idling doesn't bank more than the burst.
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(wait(&mut buckets, "a", 60, 2, much_later), None);
+        assert_eq!(wait(&mut buckets, "a", 60, 2, much_later), None);
+        assert!(wait(&mut buckets, "a", 60, 2, much_later).is_some());
+    }
+
+    #[test]
+    fn zero_rate_disables_bucket() {
+        let mut buckets = HashMap::new();
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert_eq!(wait(&mut buckets, "a", 0, 0, now), None);
+        }
+        assert!(buckets.is_empty());
+    }
+
+    #[test]
+    fn lockouts_double_up_to_max() {
+        let limiter = Limiter::new(Limits {
+            lockout_after: 3,
+            lockout_base_secs: 30,
+            lockout_max_secs: 100,
+            ..Limits::default()
+        });
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        limiter.failed("alice");
+        limiter.failed("alice");
+        assert_eq!(lockout(&limiter, "alice"), None);
+        assert!(limiter.check(ip, Some("alice")).is_ok());
+
+        limiter.failed("alice");
+        assert_eq!(lockout(&limiter, "alice"), Some(30));
+        assert!(matches!(
+            limiter.check(ip, Some("alice")),
+            Err(ErrorKind::RateLimited { retry_after: 30 })
+        ));
+        assert!(limiter.check(ip, Some("bob")).is_ok());
+
+        limiter.failed("alice");
+        assert_eq!(lockout(&limiter, "alice"), Some(60));
+        limiter.failed("alice");
+        assert_eq!(lockout(&limiter, "alice"), Some(100));
+
+        limiter.succeeded("alice");
+        assert!(limiter.check(ip, Some("alice")).is_ok());
+    }
+}
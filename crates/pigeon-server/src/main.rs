@@ -1,179 +1,420 @@
+mod attachment;
+mod channel;
+mod config;
 mod eyre;
+mod message;
+mod preferences;
+mod tls;
+mod ws;
 
 use std::{
-    collections::{BTreeMap, HashMap},
-    fs,
+    net::{IpAddr, SocketAddr},
     sync::Arc,
-    time::UNIX_EPOCH,
 };
 
 use axum::{
-    http::StatusCode,
-    routing::{get, post},
-    Extension, Json, Router, Server,
+    extract::ConnectInfo,
+    headers::{authorization::Bearer, Authorization},
+    middleware,
+    routing::{get, patch, post, put},
+    Extension, Json, Router, TypedHeader,
 };
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use clap::Parser;
 use eyre::Result;
 use parking_lot::RwLock;
-use serde::Deserialize;
+use tokio::sync::watch;
 
-use pigeon_server::*;
-
-const USERS_FILE: &str = "users.json";
-const MESSAGES_FILE: &str = "messages.json";
-const BCRYPT_SALT: [u8; 16] = *b"Hello, world!!!!";
-const BCRYPT_COST: u32 = 14;
+use config::{Args, Backend, Config, LogFormat};
+use pigeon_protocol::{LoginRequest, RecvRequest, RegisterRequest, SendRequest};
+use pigeon_server::{
+    blob::BlobStore,
+    limit::Limiter,
+    password::{HashPool, HashScheme},
+    storage::{JsonStorage, SqliteStorage, Storage},
+    *,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
 
-    #[cfg(debug_assertions)]
-    tracing_subscriber::fmt().pretty().init();
-
-    #[cfg(not(debug_assertions))]
-    tracing_subscriber::fmt().compact().init();
-
-    let state = {
-        let users = serde_json::from_str(&fs::read_to_string(USERS_FILE).unwrap_or_else(|err| {
-            tracing::warn!("Error reading {}: {}, using new userlist", USERS_FILE, err);
-            String::new()
-        }))
-        .unwrap_or_else(|err| {
-            tracing::warn!("Error parsing {}: {}, using new userlist", USERS_FILE, err);
-            HashMap::new()
-        });
-
-        let messages =
-            serde_json::from_str(&fs::read_to_string(MESSAGES_FILE).unwrap_or_else(|err| {
-                tracing::warn!(
-                    "Error reading {}: {}, using new messagelist",
-                    MESSAGES_FILE,
-                    err
-                );
-                String::new()
-            }))
-            .unwrap_or_else(|err| {
-                tracing::warn!(
-                    "Error parsing {}: {}, using new messages list",
-                    MESSAGES_FILE,
-                    err
-                );
-                BTreeMap::new()
-            });
-
-        Arc::new(RwLock::new(State { users, messages }))
+    let args = Args::parse();
+    let config = Arc::new(Config::load(&args)?);
+    if args.print_config {
+        print!("{}", toml::to_string_pretty(&config)?);
+        return Ok(());
+    }
+    if args.check {
+        println!("Configuration OK");
+        return Ok(());
+    }
+
+    match config.log_format {
+        LogFormat::Pretty => tracing_subscriber::fmt().pretty().init(),
+        LogFormat::Compact => tracing_subscriber::fmt().compact().init(),
+    }
+
+    let storage: Box<dyn Storage> = match config.storage.backend {
+        Backend::Sqlite => Box::new(SqliteStorage::open(&config.storage.sqlite_file)?),
+        Backend::Json => Box::new(JsonStorage::new(
+            &config.storage.users_file,
+            &config.storage.messages_file,
+            &config.storage.sessions_file,
+            &config.storage.channels_file,
+            &config.storage.receipts_file,
+            &config.storage.preferences_file,
+            &config.storage.log_file,
+        )),
+    };
+    let state = Arc::new(RwLock::new(State::load(storage)?));
+    let blobs = Arc::new(BlobStore::new(
+        &config.attachments.dir,
+        config.attachments.max_size,
+    )?);
+    let passwords = Passwords {
+        scheme: config.password_hash(),
+        pool: HashPool::new(config.password.hash_workers, config.password.hash_queue)?,
+        limiter: Arc::new(Limiter::new(config.limits)),
     };
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
 
     let app = Router::new()
         .route("/", get(|| async { "Hello, world!" }))
         .route("/register", post(register))
+        .route("/login", post(login))
+        .route("/logout", post(logout))
+        .route("/refresh", post(refresh))
         .route("/message", post(send).get(recv))
-        .layer(Extension(Arc::clone(&state)));
+        .route("/message/:id", patch(message::edit).delete(message::delete))
+        .route("/message/:id/thread", get(message::thread))
+        .route("/message/:id/receipts", get(message::receipts))
+        .route("/ack", post(message::ack))
+        .route("/cursor", get(message::cursor))
+        .route("/search", get(message::search))
+        .route("/attachment", post(attachment::upload))
+        .route("/message/:id/attachment/:hash", get(attachment::download))
+        .route("/preferences", get(preferences::get).put(preferences::set))
+        .route(
+            "/message/:id/reaction",
+            put(message::react).delete(message::unreact),
+        )
+        .route("/ws", get(ws::ws))
+        .route("/channel", post(channel::create).get(channel::list))
+        .route("/channel/:id/rename", post(channel::rename))
+        .route("/channel/:id/invite", post(channel::invite))
+        .route("/channel/:id/leave", post(channel::leave))
+        .layer(Extension(Arc::clone(&state)))
+        .layer(Extension(Arc::clone(&config)))
+        .layer(Extension(blobs))
+        .layer(Extension(passwords))
+        .layer(Extension(shutdown_rx))
+        .layer(middleware::from_fn(json_errors));
 
-    tokio::task::spawn(
-        Server::bind(&"0.0.0.0:3000".parse().unwrap()).serve(app.into_make_service()),
-    );
+    tokio::task::spawn({
+        let state = Arc::clone(&state);
+        let compact_interval = config.compact_interval();
+        async move {
+            let mut interval = tokio::time::interval(compact_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let compaction = match state.write().start_compaction() {
+                    Ok(Some(compaction)) => compaction,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        tracing::warn!("Error compacting storage: {}", err);
+                        continue;
+                    }
+                };
+                match tokio::task::spawn_blocking(compaction).await {
+                    Ok(Err(err)) => tracing::warn!("Error compacting storage: {}", err),
+                    Err(err) => tracing::warn!("Compaction task failed: {}", err),
+                    Ok(Ok(())) => {}
+                }
+            }
+        }
+    });
 
-    tokio::signal::ctrl_c().await?;
+    let handle = Handle::new();
+    let mut servers = Vec::new();
+    match (&config.tls.cert, &config.tls.key) {
+        (Some(cert), Some(key)) => {
+            let rustls = RustlsConfig::from_pem_file(cert, key).await?;
+            tokio::task::spawn(tls::reload_on_sighup(
+                rustls.clone(),
+                cert.clone(),
+                key.clone(),
+            ));
+            if let Some(redirect_bind) = config.tls.redirect_bind {
+                servers.push(tokio::task::spawn(
+                    axum_server::bind(redirect_bind)
+                        .handle(handle.clone())
+                        .serve(tls::redirect(config.bind.port()).into_make_service()),
+                ));
+            }
+            servers.push(tokio::task::spawn(
+                axum_server::bind_rustls(config.bind, rustls)
+                    .handle(handle.clone())
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>()),
+            ));
+        }
+        _ => {
+            servers.push(tokio::task::spawn(
+                axum_server::bind(config.bind)
+                    .handle(handle.clone())
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>()),
+            ));
+        }
+    }
+
+    shutdown_signal().await?;
+
+    let timeout = config.shutdown_timeout();
+    tracing::info!(
+        "Shutting down, draining connections for up to {:?}",
+        timeout
+    );
+    handle.graceful_shutdown(Some(timeout));
+    let _ = shutdown_tx.send(());
+    let drained = tokio::time::timeout(timeout, async {
+        for server in servers {
+            match server.await {
+                Ok(Err(err)) => tracing::warn!("Server error: {}", err),
+                Err(err) => tracing::warn!("Server task failed: {}", err),
+                Ok(Ok(())) => {}
+            }
+        }
+        // Every WebSocket stream holds a receiver, so this resolves once they've all closed.
+        shutdown_tx.closed().await;
+    })
+    .await;
+    if drained.is_err() {
+        tracing::warn!("Drain timeout elapsed, dropping remaining connections");
+    }
 
-    fs::write(
-        USERS_FILE,
-        serde_json::to_string(&Arc::clone(&state).read().users)?,
-    )?;
-    fs::write(
-        MESSAGES_FILE,
-        serde_json::to_string(&Arc::clone(&state).read().messages)?,
-    )?;
+    state.write().flush()?;
+    tracing::info!("Storage flushed, bye");
 
     Ok(())
 }
 
-#[derive(Deserialize)]
-struct RegInfo {
-    username: String,
-    password: String,
+#[derive(Clone)]
+struct Passwords {
+    scheme: HashScheme,
+    pool: HashPool,
+    limiter: Arc<Limiter>,
+}
+
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
 }
 
 async fn register(
-    Json(reg_info): Json<RegInfo>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(reg_info): Json<RegisterRequest>,
     Extension(state): Extension<Arc<RwLock<State>>>,
-) -> Result<(), StatusCode> {
+    Extension(passwords): Extension<Passwords>,
+) -> Result<(), AppError> {
+    passwords.limiter.check(addr.ip(), None)?;
     if state.read().users.contains_key(&reg_info.username) {
-        return Err(StatusCode::CONFLICT);
+        return Err(ErrorKind::UsernameTaken.into());
     }
 
-    let hash = match bcrypt::hash_with_salt(reg_info.password, BCRYPT_COST, BCRYPT_SALT) {
-        Ok(hash) => hash,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
-    .to_string();
-
-    state.write().users.insert(reg_info.username, hash);
+    let hash = passwords
+        .pool
+        .hash(passwords.scheme, reg_info.password)
+        .await?;
 
-    Ok(())
+    Ok(state.write().add_user(reg_info.username, hash)?)
 }
 
-#[derive(Deserialize)]
-struct SendInfo {
-    password: String,
-    message: Message,
+async fn login(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(login_info): Json<LoginRequest>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+    Extension(passwords): Extension<Passwords>,
+) -> Result<Json<Session>, AppError> {
+    check_password(
+        &state,
+        &passwords,
+        addr.ip(),
+        &login_info.username,
+        &login_info.password,
+    )
+    .await?;
+
+    Ok(Json(state.write().create_session(&login_info.username)?))
 }
 
-async fn send(
-    Json(send_info): Json<SendInfo>,
+async fn logout(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
     Extension(state): Extension<Arc<RwLock<State>>>,
-) -> Result<(), StatusCode> {
-    if !auth(
-        &state.read(),
-        &send_info.message.author,
-        &send_info.password,
-    )
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    {
-        return Err(StatusCode::UNAUTHORIZED);
+) -> Result<(), AppError> {
+    if !state.write().revoke_session(bearer.token())? {
+        return Err(ErrorKind::InvalidSession.into());
     }
 
-    for user in &send_info.message.recipients {
-        if !state.read().users.contains_key(user) {
-            return Err(StatusCode::NOT_ACCEPTABLE);
-        }
-    }
+    Ok(())
+}
 
+async fn refresh(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Session>, AppError> {
     state
         .write()
-        .add_message_at_present(send_info.message)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .refresh_session(bearer.token())?
+        .map(Json)
+        .ok_or_else(|| ErrorKind::InvalidSession.into())
+}
+
+async fn authenticate(
+    state: &RwLock<State>,
+    passwords: &Passwords,
+    ip: IpAddr,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    username: &str,
+    password: Option<&str>,
+) -> Result<String, AppError> {
+    if let Some(TypedHeader(Authorization(bearer))) = bearer {
+        return match session_user(state, bearer.token())? {
+            user if username.is_empty() || user == username => Ok(user),
+            _ => Err(ErrorKind::InvalidSession.into()),
+        };
+    }
+
+    check_password(
+        state,
+        passwords,
+        ip,
+        username,
+        password.ok_or(ErrorKind::BadCredentials)?,
+    )
+    .await?;
+
+    Ok(username.to_string())
 }
 
-#[derive(Deserialize)]
-struct RecvInfo {
-    username: String,
-    password: String,
-    timestamp: u64,
+fn session_user(state: &RwLock<State>, token: &str) -> Result<String, AppError> {
+    state
+        .read()
+        .session_user(token)?
+        .map(str::to_string)
+        .ok_or_else(|| ErrorKind::InvalidSession.into())
 }
 
-async fn recv(
-    Json(recv_info): Json<RecvInfo>,
+async fn check_password(
+    state: &RwLock<State>,
+    passwords: &Passwords,
+    ip: IpAddr,
+    username: &str,
+    password: &str,
+) -> Result<(), AppError> {
+    passwords.limiter.check(ip, Some(username))?;
+    if !auth(state, &passwords.pool, username, password).await? {
+        passwords.limiter.failed(username);
+        return Err(ErrorKind::BadCredentials.into());
+    }
+    passwords.limiter.succeeded(username);
+
+    let scheme = passwords.scheme;
+    let stale = state
+        .read()
+        .users
+        .get(username)
+        .is_some_and(|hash| !scheme.produced(hash));
+    if stale {
+        match passwords.pool.hash(scheme, password.to_string()).await {
+            Ok(hash) => {
+                if let Err(err) = state.write().set_user(username.to_string(), hash) {
+                    tracing::warn!("Error storing rehashed password for {}: {}", username, err);
+                }
+            }
+            Err(err) => tracing::warn!("Error rehashing password for {}: {}", username, err),
+        }
+    }
+
+    Ok(())
+}
+
+async fn send(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    Json(mut send_info): Json<SendRequest>,
     Extension(state): Extension<Arc<RwLock<State>>>,
-) -> Result<Json<Vec<(u64, Message)>>, StatusCode> {
-    if !auth(&state.read(), &recv_info.username, &recv_info.password)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    {
-        return Err(StatusCode::UNAUTHORIZED);
+    Extension(blobs): Extension<Arc<BlobStore>>,
+    Extension(passwords): Extension<Passwords>,
+) -> Result<Json<Arc<Message>>, AppError> {
+    send_info.message.author = authenticate(
+        &state,
+        &passwords,
+        addr.ip(),
+        bearer,
+        &send_info.message.author,
+        send_info.password.as_deref(),
+    )
+    .await?;
+
+    let attachments = &mut send_info.message.attachments;
+    if attachments.len() > MAX_ATTACHMENTS {
+        return Err(ErrorKind::TooManyAttachments {
+            limit: MAX_ATTACHMENTS,
+        }
+        .into());
+    }
+    let author = &send_info.message.author;
+    for attachment in attachments {
+        // Knowing a hash isn't enough: it has to be the author's own upload or already shared
+        // with them, or anyone could read a file by attaching it to a message to themselves.
+        let shared = state.read().can_see_attachment(author, &attachment.hash);
+        if !shared && !blobs.uploaded_by(&attachment.hash, author).await? {
+            return Err(ErrorKind::NonExistentAttachment.into());
+        }
+        *attachment = blobs.resolve(attachment).await?;
     }
 
-    let current_time = UNIX_EPOCH.elapsed().unwrap().as_secs();
     Ok(Json(
-        state
-            .read()
-            .messages
-            .iter()
-            .filter(|(&ts, _)| ts < current_time && ts > recv_info.timestamp)
-            .flat_map(|(&ts, msgs)| {
-                msgs.iter()
-                    .filter(|msg| msg.recipients.contains(&recv_info.username))
-                    .map(move |msg| (ts, msg.clone()))
-            })
-            .collect::<Vec<_>>(),
+        state.write().add_message_at_present(send_info.message)?,
     ))
 }
+
+async fn recv(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    Json(recv_info): Json<RecvRequest>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(passwords): Extension<Passwords>,
+) -> Result<Json<Vec<Arc<Message>>>, AppError> {
+    let username = authenticate(
+        &state,
+        &passwords,
+        addr.ip(),
+        bearer,
+        &recv_info.username,
+        recv_info.password.as_deref(),
+    )
+    .await?;
+
+    let limit = recv_info
+        .limit
+        .unwrap_or(config.recv_limit)
+        .min(config.recv_limit);
+    let messages = state.read().inbox(&username, recv_info.after, limit);
+    state.write().mark_delivered(&username, &messages)?;
+    Ok(Json(messages))
+}
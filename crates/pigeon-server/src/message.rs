@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    headers::{authorization::Bearer, Authorization},
+    Extension, Json, TypedHeader,
+};
+use parking_lot::RwLock;
+
+use pigeon_protocol::{AckRequest, EditRequest, ReactionRequest, ThreadParams};
+use pigeon_server::{AppError, Message, Receipt, SearchParams, SearchResults, State, Thread};
+
+use crate::{config::Config, session_user};
+
+pub async fn edit(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<u64>,
+    Json(edit_info): Json<EditRequest>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Arc<Message>>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.write().edit_message(
+        id,
+        &username,
+        edit_info.content,
+    )?))
+}
+
+pub async fn delete(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<u64>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Arc<Message>>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.write().delete_message(id, &username)?))
+}
+
+pub async fn react(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<u64>,
+    Json(reaction_info): Json<ReactionRequest>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Arc<Message>>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.write().add_reaction(
+        id,
+        &username,
+        reaction_info.emoji,
+    )?))
+}
+
+pub async fn unreact(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<u64>,
+    Json(reaction_info): Json<ReactionRequest>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Arc<Message>>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.write().remove_reaction(
+        id,
+        &username,
+        reaction_info.emoji,
+    )?))
+}
+
+pub async fn thread(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<u64>,
+    Query(params): Query<ThreadParams>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+    Extension(config): Extension<Arc<Config>>,
+) -> Result<Json<Thread>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    let limit = params
+        .limit
+        .unwrap_or(config.recv_limit)
+        .min(config.recv_limit);
+    Ok(Json(state.read().thread(
+        id,
+        &username,
+        params.after,
+        limit,
+    )?))
+}
+
+pub async fn search(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Query(params): Query<SearchParams>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+    Extension(config): Extension<Arc<Config>>,
+) -> Result<Json<SearchResults>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    let limit = params
+        .limit
+        .unwrap_or(config.recv_limit)
+        .min(config.recv_limit);
+    Ok(Json(state.read().search(&username, &params, limit)))
+}
+
+pub async fn receipts(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<u64>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Vec<Receipt>>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.read().receipts(id, &username)?))
+}
+
+/// Where a stream should start to skip the backlog, without marking anything delivered.
+pub async fn cursor(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<u64>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.read().latest_revision(&username)))
+}
+
+pub async fn ack(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Json(ack_info): Json<AckRequest>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<(), AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(state.write().mark_read(&username, &ack_info.messages)?)
+}
@@ -0,0 +1,171 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use std::{
+    sync::{
+        mpsc::{self, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+};
+
+use parking_lot::Mutex;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::{
+    eyre::{bail, eyre, Result},
+    ErrorKind,
+};
+
+/// The salt every bcrypt hash shared before hashes were salted per user, as bcrypt encodes it.
+const LEGACY_BCRYPT_SALT: &str = "QETqZE6qGFbtakviGQCfGO";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum HashScheme {
+    Bcrypt {
+        cost: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl HashScheme {
+    pub fn hash(&self, password: &str) -> Result<String> {
+        match *self {
+            Self::Bcrypt { cost } => Ok(bcrypt::hash(password, cost)?),
+            Self::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params = Params::new(memory_kib, iterations, parallelism, None)?;
+                let salt = SaltString::generate(&mut OsRng);
+                Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password(password.as_bytes(), &salt)?
+                    .to_string())
+            }
+        }
+    }
+
+    /// Whether `hash` was produced by this scheme with these exact parameters.
+    pub fn produced(&self, hash: &str) -> bool {
+        match *self {
+            Self::Bcrypt { cost } => {
+                bcrypt_cost(hash) == Some(cost) && bcrypt_salt(hash) != Some(LEGACY_BCRYPT_SALT)
+            }
+            Self::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => PasswordHash::new(hash)
+                .ok()
+                .filter(|hash| hash.algorithm == Algorithm::Argon2id.ident())
+                .and_then(|hash| Params::try_from(&hash).ok())
+                .is_some_and(|params| {
+                    params.m_cost() == memory_kib
+                        && params.t_cost() == iterations
+                        && params.p_cost() == parallelism
+                }),
+        }
+    }
+}
+
+pub fn verify(password: &str, hash: &str) -> Result<bool> {
+    if bcrypt_cost(hash).is_some() {
+        return Ok(bcrypt::verify(password, hash)?);
+    }
+
+    let parsed = match PasswordHash::new(hash) {
+        Ok(parsed) => parsed,
+        Err(err) => bail!("Unrecognised password hash: {}", err),
+    };
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok())
+}
+
+fn bcrypt_cost(hash: &str) -> Option<u32> {
+    let mut parts = hash.split('$').skip(1);
+    match (parts.next(), parts.next()) {
+        (Some("2a" | "2b" | "2x" | "2y"), Some(cost)) => cost.parse().ok(),
+        _ => None,
+    }
+}
+
+fn bcrypt_salt(hash: &str) -> Option<&str> {
+    hash.split('$').nth(3).and_then(|rest| rest.get(..22))
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads for password hashing, so slow hashes never run on the async runtime.
+#[derive(Debug, Clone)]
+pub struct HashPool {
+    jobs: SyncSender<Job>,
+}
+
+impl HashPool {
+    /// `queue` jobs may wait for a free worker; beyond that, work is refused as overloaded.
+    pub fn new(workers: usize, queue: usize) -> Result<Self> {
+        let (jobs, receiver) = mpsc::sync_channel::<Job>(queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("pigeon-hash-{index}"))
+                .spawn(move || loop {
+                    let job = receiver.lock().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => return,
+                    }
+                })?;
+        }
+        Ok(Self { jobs })
+    }
+
+    pub async fn hash(&self, scheme: HashScheme, password: String) -> Result<String> {
+        self.run(move || scheme.hash(&password)).await
+    }
+
+    pub async fn verify(&self, password: String, hash: String) -> Result<bool> {
+        self.run(move || verify(&password, &hash)).await
+    }
+
+    async fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce() -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let (sender, receiver) = oneshot::channel();
+        match self.jobs.try_send(Box::new(move || {
+            let _ = sender.send(job());
+        })) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => bail!(ErrorKind::Overloaded),
+            Err(TrySendError::Disconnected(_)) => bail!("Hashing pool has shut down"),
+        }
+        receiver.await.map_err(|_| eyre!("Hashing job panicked"))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_bcrypt_salt_is_stale() {
+        let legacy = bcrypt::hash_with_salt("password", 4, *b"Hello, world!!!!").unwrap();
+        assert_eq!(legacy.get_salt(), LEGACY_BCRYPT_SALT);
+
+        let scheme = HashScheme::Bcrypt { cost: 4 };
+        assert!(!scheme.produced(&legacy.to_string()));
+        assert!(scheme.produced(&scheme.hash("password").unwrap()));
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use axum::{
+    headers::{authorization::Bearer, Authorization},
+    Extension, Json, TypedHeader,
+};
+use parking_lot::RwLock;
+
+use pigeon_server::{AppError, Preferences, State};
+
+use crate::session_user;
+
+pub async fn get(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Preferences>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.read().preferences(&username)))
+}
+
+pub async fn set(
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Json(preferences): Json<Preferences>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+) -> Result<Json<Preferences>, AppError> {
+    let username = session_user(&state, bearer.token())?;
+
+    Ok(Json(state.write().set_preferences(&username, preferences)?))
+}
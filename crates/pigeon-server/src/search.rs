@@ -0,0 +1,192 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Words are runs of alphanumerics, compared case-insensitively.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// A parsed search string, where anything between double quotes has to appear in that order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub terms: Vec<String>,
+    pub phrases: Vec<Vec<String>>,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Self {
+        let mut parsed = Self::default();
+        for (index, part) in query.split('"').enumerate() {
+            let words = tokenize(part).collect::<Vec<_>>();
+            if index % 2 == 1 && words.len() > 1 {
+                parsed.phrases.push(words.clone());
+            }
+            parsed.terms.extend(words);
+        }
+        parsed.terms.sort_unstable();
+        parsed.terms.dedup();
+        parsed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn matches_phrases(&self, content: &str) -> bool {
+        if self.phrases.is_empty() {
+            return true;
+        }
+        let words = tokenize(content).collect::<Vec<_>>();
+        self.phrases.iter().all(|phrase| {
+            words
+                .windows(phrase.len())
+                .any(|window| window == phrase.as_slice())
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Index {
+    postings: HashMap<String, BTreeMap<u64, u32>>,
+    documents: usize,
+}
+
+impl Index {
+    pub fn insert(&mut self, id: u64, content: &str) {
+        let mut indexed = false;
+        for word in tokenize(content) {
+            *self
+                .postings
+                .entry(word)
+                .or_default()
+                .entry(id)
+                .or_default() += 1;
+            indexed = true;
+        }
+        if indexed {
+            self.documents += 1;
+        }
+    }
+
+    pub fn remove(&mut self, id: u64, content: &str) {
+        let mut removed = false;
+        for word in tokenize(content) {
+            let Some(posting) = self.postings.get_mut(&word) else {
+                continue;
+            };
+            removed |= posting.remove(&id).is_some();
+            if posting.is_empty() {
+                self.postings.remove(&word);
+            }
+        }
+        if removed {
+            self.documents -= 1;
+        }
+    }
+
+    /// Ids containing every term, scored by tf-idf so rarer words count for more.
+    pub fn search(&self, query: &Query) -> Vec<(u64, f64)> {
+        let mut postings = Vec::with_capacity(query.terms.len());
+        for term in &query.terms {
+            match self.postings.get(term) {
+                Some(posting) => postings.push(posting),
+                None => return Vec::new(),
+            }
+        }
+        postings.sort_unstable_by_key(|posting| posting.len());
+        let Some((rarest, rest)) = postings.split_first() else {
+            return Vec::new();
+        };
+
+        rarest
+            .keys()
+            .filter(|id| rest.iter().all(|posting| posting.contains_key(id)))
+            .map(|&id| {
+                let score = postings
+                    .iter()
+                    .map(|posting| {
+                        let idf = (1.0 + self.documents as f64 / posting.len() as f64).ln();
+                        f64::from(posting[&id]) * idf
+                    })
+                    .sum();
+                (id, score)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(documents: &[(u64, &str)]) -> Index {
+        let mut index = Index::default();
+        for (id, content) in documents {
+            index.insert(*id, content);
+        }
+        index
+    }
+
+    fn score(results: &[(u64, f64)], id: u64) -> f64 {
+        results
+            .iter()
+            .find(|(result, _)| *result == id)
+            .map(|(_, score)| *score)
+            .unwrap()
+    }
+
+    #[test]
+    fn quoted_words_are_phrases() {
+        let query = Query::parse(r#"Pigeon "carrier  PIGEON" "post""#);
+        assert_eq!(query.terms, ["carrier", "pigeon", "post"]);
+        assert_eq!(query.phrases, [vec!["carrier", "pigeon"]]);
+
+        assert!(query.matches_phrases("a carrier pigeon"));
+        assert!(query.matches_phrases("Carrier, pigeon!"));
+        assert!(!query.matches_phrases("pigeon carrier"));
+        assert!(!query.matches_phrases("carrier of a pigeon"));
+        assert!(Query::parse("pigeon carrier").matches_phrases("pigeon of a carrier"));
+    }
+
+    #[test]
+    fn search_needs_every_term() {
+        let index = index(&[(1, "hello world"), (2, "hello there"), (3, "world")]);
+
+        let results = index.search(&Query::parse("hello world"));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, 1);
+        assert!(index.search(&Query::parse("hello nobody")).is_empty());
+        assert!(index.search(&Query::default()).is_empty());
+    }
+
+    #[test]
+    fn rare_and_repeated_words_score_higher() {
+        let index = index(&[
+            (1, "lunch today"),
+            (2, "lunch lunch lunch"),
+            (3, "lunch at the pier"),
+            (4, "the pier"),
+        ]);
+
+        let results = index.search(&Query::parse("lunch"));
+        assert!(score(&results, 2) > score(&results, 1));
+
+        // Message 3 has one of each, but "pier" is in fewer messages than "lunch".
+        let results = index.search(&Query::parse("pier"));
+        let lunch = index.search(&Query::parse("lunch"));
+        assert!(score(&results, 3) > score(&lunch, 3));
+    }
+
+    #[test]
+    fn removed_documents_stop_matching() {
+        let mut index = index(&[(1, "hello world"), (2, "hello")]);
+        index.remove(1, "hello world");
+
+        assert!(index.search(&Query::parse("world")).is_empty());
+        assert!(!index.postings.contains_key("world"));
+        assert_eq!(index.documents, 1);
+        let results = index.search(&Query::parse("hello"));
+        assert_eq!(results.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [2]);
+    }
+}
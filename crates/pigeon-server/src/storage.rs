@@ -0,0 +1,59 @@
+mod json;
+mod sqlite;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    sync::Arc,
+};
+
+pub use json::JsonStorage;
+pub use sqlite::SqliteStorage;
+
+use crate::{eyre::Result, Channel, Message, Preferences, Receipt, Session};
+
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    pub users: HashMap<String, String>,
+    pub messages: BTreeMap<u64, Message>,
+    pub sessions: HashMap<String, Session>,
+    pub channels: BTreeMap<u64, Channel>,
+    pub receipts: BTreeMap<u64, BTreeMap<String, Receipt>>,
+    pub preferences: HashMap<String, Preferences>,
+}
+
+/// What a compaction writes out. Messages are shared with the state, so taking one while it's
+/// locked is cheap.
+#[derive(Debug, Default)]
+pub struct Dump {
+    pub users: HashMap<String, String>,
+    pub messages: BTreeMap<u64, Arc<Message>>,
+    pub sessions: HashMap<String, Session>,
+    pub channels: BTreeMap<u64, Channel>,
+    pub receipts: BTreeMap<u64, BTreeMap<String, Receipt>>,
+    pub preferences: HashMap<String, Preferences>,
+}
+
+/// The slow half of a compaction, run once the state is unlocked.
+pub type Compaction = Box<dyn FnOnce(&Dump) -> Result<()> + Send>;
+
+pub trait Storage: Debug + Send + Sync {
+    fn load(&mut self) -> Result<Snapshot>;
+    fn put_user(&mut self, username: &str, hash: &str) -> Result<()>;
+    fn put_message(&mut self, message: &Message) -> Result<()>;
+    fn put_channel(&mut self, channel: &Channel) -> Result<()>;
+    fn put_session(&mut self, session: &Session) -> Result<()>;
+    fn remove_session(&mut self, token: &str) -> Result<()>;
+    fn put_receipts(&mut self, receipts: &[Receipt]) -> Result<()>;
+    fn put_preferences(&mut self, username: &str, preferences: &Preferences) -> Result<()>;
+
+    /// Sets aside what was written since the last compaction, if anything, so the returned
+    /// compaction can fold it into a dump of the state taken right after.
+    fn start_compaction(&mut self) -> Result<Option<Compaction>> {
+        Ok(None)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,538 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{Compaction, Dump, Snapshot, Storage};
+use crate::{
+    eyre::{eyre, Result},
+    Channel, Message, Preferences, Receipt, Session,
+};
+
+#[derive(Debug)]
+pub struct JsonStorage {
+    files: SnapshotFiles,
+    log_file: PathBuf,
+    /// The log set aside by a compaction until its snapshot is written.
+    compacting_file: PathBuf,
+    log: Option<File>,
+    appended: usize,
+    compacting: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Clone)]
+struct SnapshotFiles {
+    users: PathBuf,
+    messages: PathBuf,
+    sessions: PathBuf,
+    channels: PathBuf,
+    receipts: PathBuf,
+    preferences: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum LogEntry {
+    PutUser {
+        username: String,
+        hash: String,
+    },
+    PutMessage {
+        message: Message,
+    },
+    PutChannel {
+        channel: Channel,
+    },
+    PutSession {
+        session: Session,
+    },
+    RemoveSession {
+        token: String,
+    },
+    PutReceipts {
+        receipts: Vec<Receipt>,
+    },
+    PutPreferences {
+        username: String,
+        preferences: Preferences,
+    },
+}
+
+// Map keys are kept as strings because untagged enums can't parse them as integers.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MessagesFile {
+    Legacy(BTreeMap<String, Vec<Message>>),
+    Current(BTreeMap<String, Message>),
+}
+
+impl Default for MessagesFile {
+    fn default() -> Self {
+        Self::Current(BTreeMap::new())
+    }
+}
+
+impl From<MessagesFile> for BTreeMap<u64, Message> {
+    fn from(file: MessagesFile) -> Self {
+        match file {
+            MessagesFile::Current(messages) => messages
+                .into_values()
+                .map(|message| (message.id, message))
+                .collect(),
+            MessagesFile::Legacy(messages) => messages
+                .into_iter()
+                .filter_map(|(timestamp, messages)| Some((timestamp.parse().ok()?, messages)))
+                .collect::<BTreeMap<u64, _>>()
+                .into_iter()
+                .flat_map(|(timestamp, messages)| {
+                    messages.into_iter().map(move |message| Message {
+                        timestamp,
+                        ..message
+                    })
+                })
+                .zip(1..)
+                .map(|(message, id)| (id, Message { id, ..message }))
+                .collect(),
+        }
+    }
+}
+
+impl JsonStorage {
+    pub fn new(
+        users_file: impl Into<PathBuf>,
+        messages_file: impl Into<PathBuf>,
+        sessions_file: impl Into<PathBuf>,
+        channels_file: impl Into<PathBuf>,
+        receipts_file: impl Into<PathBuf>,
+        preferences_file: impl Into<PathBuf>,
+        log_file: impl Into<PathBuf>,
+    ) -> Self {
+        let log_file = log_file.into();
+        let mut compacting_file = log_file.clone().into_os_string();
+        compacting_file.push(".compacting");
+
+        Self {
+            files: SnapshotFiles {
+                users: users_file.into(),
+                messages: messages_file.into(),
+                sessions: sessions_file.into(),
+                channels: channels_file.into(),
+                receipts: receipts_file.into(),
+                preferences: preferences_file.into(),
+            },
+            log_file,
+            compacting_file: compacting_file.into(),
+            log: None,
+            appended: 0,
+            compacting: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn append(&mut self, entry: LogEntry) -> Result<()> {
+        let log = match &mut self.log {
+            Some(log) => log,
+            None => {
+                let log = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.log_file)?;
+                sync_parent(&self.log_file)?;
+                self.log.insert(log)
+            }
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        log.write_all(&line)?;
+        log.sync_data()?;
+
+        self.appended += 1;
+        Ok(())
+    }
+
+    /// Folds the current log into the one an earlier, failed compaction left behind.
+    fn merge_into_compacting(&self) -> Result<()> {
+        let log = match fs::read(&self.log_file) {
+            Ok(log) => log,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut compacting = OpenOptions::new()
+            .append(true)
+            .open(&self.compacting_file)?;
+        compacting.write_all(&log)?;
+        compacting.sync_data()?;
+        fs::remove_file(&self.log_file)?;
+        sync_parent(&self.log_file)?;
+
+        Ok(())
+    }
+}
+
+impl LogEntry {
+    fn apply(self, snapshot: &mut Snapshot) {
+        match self {
+            Self::PutUser { username, hash } => {
+                snapshot.users.insert(username, hash);
+            }
+            Self::PutMessage { message } => {
+                snapshot.messages.insert(message.id, message);
+            }
+            Self::PutChannel { channel } => {
+                snapshot.channels.insert(channel.id, channel);
+            }
+            Self::PutSession { session } => {
+                snapshot.sessions.insert(session.token.clone(), session);
+            }
+            Self::RemoveSession { token } => {
+                snapshot.sessions.remove(&token);
+            }
+            Self::PutReceipts { receipts } => {
+                for receipt in receipts {
+                    snapshot
+                        .receipts
+                        .entry(receipt.message)
+                        .or_default()
+                        .insert(receipt.user.clone(), receipt);
+                }
+            }
+            Self::PutPreferences {
+                username,
+                preferences,
+            } => {
+                snapshot.preferences.insert(username, preferences);
+            }
+        }
+    }
+}
+
+impl SnapshotFiles {
+    fn read(&self) -> Snapshot {
+        Snapshot {
+            users: read_or_default(&self.users),
+            messages: read_or_default::<MessagesFile>(&self.messages).into(),
+            sessions: read_or_default(&self.sessions),
+            channels: read_or_default(&self.channels),
+            receipts: read_or_default(&self.receipts),
+            preferences: read_or_default(&self.preferences),
+        }
+    }
+
+    fn write(&self, dump: &Dump) -> Result<()> {
+        write_atomic(&self.users, &dump.users)?;
+        write_atomic(&self.messages, &dump.messages)?;
+        write_atomic(&self.sessions, &dump.sessions)?;
+        write_atomic(&self.channels, &dump.channels)?;
+        write_atomic(&self.receipts, &dump.receipts)?;
+        write_atomic(&self.preferences, &dump.preferences)
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load(&mut self) -> Result<Snapshot> {
+        let mut snapshot = self.files.read();
+        // Whatever an unfinished compaction set aside is older than the current log.
+        self.appended =
+            replay(&self.compacting_file, &mut snapshot)? + replay(&self.log_file, &mut snapshot)?;
+
+        Ok(snapshot)
+    }
+
+    fn put_user(&mut self, username: &str, hash: &str) -> Result<()> {
+        self.append(LogEntry::PutUser {
+            username: username.to_string(),
+            hash: hash.to_string(),
+        })
+    }
+
+    fn put_message(&mut self, message: &Message) -> Result<()> {
+        self.append(LogEntry::PutMessage {
+            message: message.clone(),
+        })
+    }
+
+    fn put_channel(&mut self, channel: &Channel) -> Result<()> {
+        self.append(LogEntry::PutChannel {
+            channel: channel.clone(),
+        })
+    }
+
+    fn put_session(&mut self, session: &Session) -> Result<()> {
+        self.append(LogEntry::PutSession {
+            session: session.clone(),
+        })
+    }
+
+    fn remove_session(&mut self, token: &str) -> Result<()> {
+        self.append(LogEntry::RemoveSession {
+            token: token.to_string(),
+        })
+    }
+
+    fn put_receipts(&mut self, receipts: &[Receipt]) -> Result<()> {
+        self.append(LogEntry::PutReceipts {
+            receipts: receipts.to_vec(),
+        })
+    }
+
+    fn put_preferences(&mut self, username: &str, preferences: &Preferences) -> Result<()> {
+        self.append(LogEntry::PutPreferences {
+            username: username.to_string(),
+            preferences: preferences.clone(),
+        })
+    }
+
+    fn start_compaction(&mut self) -> Result<Option<Compaction>> {
+        if self.appended == 0 || self.compacting.load(Ordering::Acquire) {
+            return Ok(None);
+        }
+
+        // New entries go to a fresh log, so the old one can be dropped once the dump is written.
+        self.log = None;
+        if self.compacting_file.exists() {
+            self.merge_into_compacting()?;
+        } else {
+            fs::rename(&self.log_file, &self.compacting_file)?;
+            sync_parent(&self.compacting_file)?;
+        }
+        self.appended = 0;
+        self.compacting.store(true, Ordering::Release);
+
+        let files = self.files.clone();
+        let compacting_file = self.compacting_file.clone();
+        let compacting = Arc::clone(&self.compacting);
+        Ok(Some(Box::new(move |dump| {
+            // Every snapshot rename is synced by now, so losing power can't keep the unlink
+            // without them.
+            let result = files.write(dump).and_then(|()| {
+                fs::remove_file(&compacting_file)?;
+                sync_parent(&compacting_file)
+            });
+            compacting.store(false, Ordering::Release);
+            result
+        })))
+    }
+}
+
+/// Applies every entry up to the first one a crash cut short, and truncates the log there so
+/// later appends aren't stranded behind it.
+fn replay(path: &Path, snapshot: &mut Snapshot) -> Result<usize> {
+    let log = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(log) => log,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut reader = BufReader::new(&log);
+    let mut line = Vec::new();
+    let mut replayed = 0;
+    let mut valid_len = 0;
+    loop {
+        line.clear();
+        let len = reader.read_until(b'\n', &mut line)?;
+        if len == 0 {
+            break;
+        }
+        let entry = match line.strip_suffix(b"\n") {
+            Some(line) => serde_json::from_slice::<LogEntry>(line).map_err(Into::into),
+            None => Err(eyre!("Line is cut short")),
+        };
+        match entry {
+            Ok(entry) => {
+                entry.apply(snapshot);
+                replayed += 1;
+                valid_len += len as u64;
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "Error parsing {} line {}: {}, dropping the rest of the log",
+                    path.display(),
+                    replayed + 1,
+                    err
+                );
+                log.set_len(valid_len)?;
+                log.sync_data()?;
+                break;
+            }
+        }
+    }
+    tracing::info!("Replayed {} entries from {}", replayed, path.display());
+
+    Ok(replayed)
+}
+
+fn read_or_default<T: DeserializeOwned + Default>(path: &Path) -> T {
+    let contents = fs::read_to_string(path).unwrap_or_else(|err| {
+        tracing::warn!("Error reading {}: {}, starting empty", path.display(), err);
+        String::new()
+    });
+    serde_json::from_str(&contents).unwrap_or_else(|err| {
+        tracing::warn!("Error parsing {}: {}, starting empty", path.display(), err);
+        T::default()
+    })
+}
+
+fn write_atomic<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    write_atomic_bytes(path, &serde_json::to_vec(value)?)
+}
+
+fn write_atomic_bytes(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(contents)?;
+    tmp.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    sync_parent(path)
+}
+
+/// Makes a create, rename or unlink of `path` durable, which syncing the file itself doesn't.
+fn sync_parent(path: &Path) -> Result<()> {
+    if cfg!(unix) {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!("pigeon-{:016x}", rand::random::<u64>()));
+            fs::create_dir(&path).unwrap();
+            Self(path)
+        }
+
+        fn storage(&self) -> JsonStorage {
+            JsonStorage::new(
+                self.0.join("users.json"),
+                self.0.join("messages.json"),
+                self.0.join("sessions.json"),
+                self.0.join("channels.json"),
+                self.0.join("receipts.json"),
+                self.0.join("preferences.json"),
+                self.0.join("pigeon.wal"),
+            )
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn usernames(snapshot: &Snapshot) -> Vec<&str> {
+        let mut usernames = snapshot
+            .users
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        usernames.sort_unstable();
+        usernames
+    }
+
+    #[test]
+    fn replay_drops_torn_last_line() {
+        let dir = TempDir::new();
+        let mut storage = dir.storage();
+        storage.load().unwrap();
+        storage.put_user("alice", "hash").unwrap();
+        storage.put_user("bob", "hash").unwrap();
+        drop(storage);
+
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(dir.0.join("pigeon.wal"))
+            .unwrap();
+        log.write_all(br#"{"op":"put_user","username":"ca"#)
+            .unwrap();
+        drop(log);
+
+        let mut storage = dir.storage();
+        assert_eq!(usernames(&storage.load().unwrap()), ["alice", "bob"]);
+        storage.put_user("dave", "hash").unwrap();
+        drop(storage);
+
+        // The torn line was cut off, so it doesn't hide what was appended after it.
+        let mut storage = dir.storage();
+        assert_eq!(
+            usernames(&storage.load().unwrap()),
+            ["alice", "bob", "dave"]
+        );
+    }
+
+    #[test]
+    fn legacy_messages_get_ids_by_timestamp() {
+        let dir = TempDir::new();
+        fs::write(
+            dir.0.join("messages.json"),
+            r#"{
+                "200": [{"author": "bob", "content": "third", "recipients": ["alice"]}],
+                "100": [
+                    {"author": "alice", "content": "first", "recipients": ["bob"]},
+                    {"author": "alice", "content": "second", "recipients": ["bob"]}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let messages = dir.storage().load().unwrap().messages;
+        let messages = messages
+            .iter()
+            .map(|(&key, message)| (key, message.id, message.timestamp, message.content.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                (1, 1, 100, "first"),
+                (2, 2, 100, "second"),
+                (3, 3, 200, "third")
+            ]
+        );
+    }
+
+    #[test]
+    fn compaction_keeps_writes_made_while_it_runs() {
+        let dir = TempDir::new();
+        let mut storage = dir.storage();
+        storage.load().unwrap();
+        assert!(storage.start_compaction().unwrap().is_none());
+
+        storage.put_user("alice", "hash").unwrap();
+        let compaction = storage.start_compaction().unwrap().unwrap();
+        storage.put_user("bob", "hash").unwrap();
+        assert!(storage.start_compaction().unwrap().is_none());
+
+        // Until the dump is written, the set-aside log still has everything before it.
+        assert_eq!(usernames(&dir.storage().load().unwrap()), ["alice", "bob"]);
+
+        compaction(&Dump {
+            users: HashMap::from([("alice".to_string(), "hash".to_string())]),
+            ..Dump::default()
+        })
+        .unwrap();
+        assert!(!dir.0.join("pigeon.wal.compacting").exists());
+        assert_eq!(usernames(&dir.storage().load().unwrap()), ["alice", "bob"]);
+    }
+}
@@ -0,0 +1,304 @@
+use std::path::Path;
+
+use parking_lot::Mutex;
+use rusqlite::{params, Connection};
+
+use super::{Snapshot, Storage};
+use crate::{eyre::Result, Channel, Message, Preferences, Receipt, Session};
+
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS users (
+        username TEXT PRIMARY KEY,
+        hash TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS messages (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp INTEGER NOT NULL,
+        author TEXT NOT NULL,
+        content TEXT NOT NULL,
+        recipients TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sessions (
+        token TEXT PRIMARY KEY,
+        username TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );",
+    "ALTER TABLE messages ADD COLUMN channel INTEGER;
+    CREATE TABLE channels (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        members TEXT NOT NULL
+    );",
+    "ALTER TABLE messages ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE messages ADD COLUMN edited_at INTEGER;
+    ALTER TABLE messages ADD COLUMN history TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE messages ADD COLUMN deleted INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE messages ADD COLUMN reply_to INTEGER;
+    ALTER TABLE messages ADD COLUMN thread INTEGER;",
+    "ALTER TABLE messages ADD COLUMN reactions TEXT NOT NULL DEFAULT '[]';",
+    "CREATE TABLE receipts (
+        message INTEGER NOT NULL,
+        username TEXT NOT NULL,
+        delivered_at INTEGER,
+        read_at INTEGER,
+        PRIMARY KEY (message, username)
+    );
+    CREATE TABLE preferences (
+        username TEXT PRIMARY KEY,
+        read_receipts INTEGER NOT NULL
+    );",
+    "ALTER TABLE messages ADD COLUMN attachments TEXT NOT NULL DEFAULT '[]';",
+];
+
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            conn.execute_batch(&format!(
+                "BEGIN; {migration} PRAGMA user_version = {}; COMMIT;",
+                index + 1
+            ))?;
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&mut self) -> Result<Snapshot> {
+        let conn = self.conn.get_mut();
+        let mut snapshot = Snapshot::default();
+
+        let mut stmt = conn.prepare("SELECT username, hash FROM users")?;
+        for row in stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            let (username, hash) = row?;
+            snapshot.users.insert(username, hash);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, author, content, recipients, channel,
+                revision, edited_at, history, deleted, reply_to, thread, reactions, attachments
+            FROM messages",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, Option<i64>>(5)?,
+                row.get::<_, i64>(6)?,
+                row.get::<_, Option<i64>>(7)?,
+                row.get::<_, String>(8)?,
+                row.get(9)?,
+                row.get::<_, Option<i64>>(10)?,
+                row.get::<_, Option<i64>>(11)?,
+                row.get::<_, String>(12)?,
+                row.get::<_, String>(13)?,
+            ))
+        })?;
+        for row in rows {
+            let (
+                id,
+                timestamp,
+                author,
+                content,
+                recipients,
+                channel,
+                revision,
+                edited_at,
+                history,
+                deleted,
+                reply_to,
+                thread,
+                reactions,
+                attachments,
+            ) = row?;
+            snapshot.messages.insert(
+                id as u64,
+                Message {
+                    id: id as u64,
+                    timestamp: timestamp as u64,
+                    author,
+                    content,
+                    recipients: serde_json::from_str(&recipients)?,
+                    channel: channel.map(|channel| channel as u64),
+                    revision: revision as u64,
+                    edited_at: edited_at.map(|edited_at| edited_at as u64),
+                    history: serde_json::from_str(&history)?,
+                    deleted,
+                    reply_to: reply_to.map(|reply_to| reply_to as u64),
+                    thread: thread.map(|thread| thread as u64),
+                    reactions: serde_json::from_str(&reactions)?,
+                    attachments: serde_json::from_str(&attachments)?,
+                },
+            );
+        }
+
+        let mut stmt = conn.prepare("SELECT id, name, members FROM channels")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get(1)?, row.get::<_, String>(2)?))
+        })?;
+        for row in rows {
+            let (id, name, members) = row?;
+            snapshot.channels.insert(
+                id as u64,
+                Channel {
+                    id: id as u64,
+                    name,
+                    members: serde_json::from_str(&members)?,
+                },
+            );
+        }
+
+        let mut stmt =
+            conn.prepare("SELECT message, username, delivered_at, read_at FROM receipts")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Receipt {
+                message: row.get::<_, i64>(0)? as u64,
+                user: row.get(1)?,
+                delivered_at: row.get::<_, Option<i64>>(2)?.map(|at| at as u64),
+                read_at: row.get::<_, Option<i64>>(3)?.map(|at| at as u64),
+            })
+        })?;
+        for receipt in rows {
+            let receipt = receipt?;
+            snapshot
+                .receipts
+                .entry(receipt.message)
+                .or_default()
+                .insert(receipt.user.clone(), receipt);
+        }
+
+        let mut stmt = conn.prepare("SELECT username, read_receipts FROM preferences")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                Preferences {
+                    read_receipts: row.get(1)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (username, preferences) = row?;
+            snapshot.preferences.insert(username, preferences);
+        }
+
+        let mut stmt = conn.prepare("SELECT token, username, expires_at FROM sessions")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Session {
+                token: row.get(0)?,
+                username: row.get(1)?,
+                expires_at: row.get::<_, i64>(2)? as u64,
+            })
+        })?;
+        for session in rows {
+            let session = session?;
+            snapshot.sessions.insert(session.token.clone(), session);
+        }
+
+        Ok(snapshot)
+    }
+
+    fn put_user(&mut self, username: &str, hash: &str) -> Result<()> {
+        self.conn.get_mut().execute(
+            "INSERT INTO users (username, hash) VALUES (?1, ?2)
+            ON CONFLICT (username) DO UPDATE SET hash = excluded.hash",
+            params![username, hash],
+        )?;
+        Ok(())
+    }
+
+    fn put_message(&mut self, message: &Message) -> Result<()> {
+        self.conn.get_mut().execute(
+            "INSERT OR REPLACE INTO messages (
+                id, timestamp, author, content, recipients, channel,
+                revision, edited_at, history, deleted, reply_to, thread, reactions, attachments
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                message.id as i64,
+                message.timestamp as i64,
+                message.author,
+                message.content,
+                serde_json::to_string(&message.recipients)?,
+                message.channel.map(|channel| channel as i64),
+                message.revision as i64,
+                message.edited_at.map(|edited_at| edited_at as i64),
+                serde_json::to_string(&message.history)?,
+                message.deleted,
+                message.reply_to.map(|reply_to| reply_to as i64),
+                message.thread.map(|thread| thread as i64),
+                serde_json::to_string(&message.reactions)?,
+                serde_json::to_string(&message.attachments)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn put_channel(&mut self, channel: &Channel) -> Result<()> {
+        self.conn.get_mut().execute(
+            "INSERT OR REPLACE INTO channels (id, name, members) VALUES (?1, ?2, ?3)",
+            params![
+                channel.id as i64,
+                channel.name,
+                serde_json::to_string(&channel.members)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn put_session(&mut self, session: &Session) -> Result<()> {
+        self.conn.get_mut().execute(
+            "INSERT OR REPLACE INTO sessions (token, username, expires_at) VALUES (?1, ?2, ?3)",
+            params![session.token, session.username, session.expires_at as i64],
+        )?;
+        Ok(())
+    }
+
+    fn put_receipts(&mut self, receipts: &[Receipt]) -> Result<()> {
+        let tx = self.conn.get_mut().transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO receipts (message, username, delivered_at, read_at)
+                VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for receipt in receipts {
+                stmt.execute(params![
+                    receipt.message as i64,
+                    receipt.user,
+                    receipt.delivered_at.map(|at| at as i64),
+                    receipt.read_at.map(|at| at as i64),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn put_preferences(&mut self, username: &str, preferences: &Preferences) -> Result<()> {
+        self.conn.get_mut().execute(
+            "INSERT OR REPLACE INTO preferences (username, read_receipts) VALUES (?1, ?2)",
+            params![username, preferences.read_receipts],
+        )?;
+        Ok(())
+    }
+
+    fn remove_session(&mut self, token: &str) -> Result<()> {
+        self.conn
+            .get_mut()
+            .execute("DELETE FROM sessions WHERE token = ?1", params![token])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::Host,
+    handler::Handler,
+    http::{uri::PathAndQuery, Uri},
+    response::Redirect,
+    Extension, Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+
+#[cfg(unix)]
+pub async fn reload_on_sighup(rustls: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            tracing::warn!(
+                "Error listening for SIGHUP, certificates won't reload: {}",
+                err
+            );
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        match rustls.reload_from_pem_file(&cert, &key).await {
+            Ok(()) => tracing::info!("Reloaded TLS certificate from {}", cert.display()),
+            Err(err) => tracing::warn!(
+                "Error reloading TLS certificate, keeping the old one: {}",
+                err
+            ),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn reload_on_sighup(_rustls: RustlsConfig, _cert: PathBuf, _key: PathBuf) {}
+
+pub fn redirect(https_port: u16) -> Router {
+    Router::new()
+        .fallback(to_https.into_service())
+        .layer(Extension(https_port))
+}
+
+async fn to_https(Host(host): Host, uri: Uri, Extension(https_port): Extension<u16>) -> Redirect {
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => &host,
+    };
+    let authority = match https_port {
+        443 => host.to_string(),
+        port => format!("{host}:{port}"),
+    };
+    let path = uri.path_and_query().map_or("/", PathAndQuery::as_str);
+
+    Redirect::permanent(&format!("https://{authority}{path}"))
+}
@@ -0,0 +1,157 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message as WsMessage, WebSocket, WebSocketUpgrade},
+        Query,
+    },
+    headers::{authorization::Bearer, Authorization},
+    response::Response,
+    Extension, TypedHeader,
+};
+use parking_lot::RwLock;
+use tokio::{
+    sync::{broadcast::error::RecvError, watch},
+    time::Instant,
+};
+
+use pigeon_protocol::StreamParams;
+use pigeon_server::{AppError, ErrorKind, Event, Message, State};
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+const PONG_TIMEOUT: Duration = Duration::from_secs(90);
+
+pub async fn ws(
+    upgrade: WebSocketUpgrade,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    Query(params): Query<StreamParams>,
+    Extension(state): Extension<Arc<RwLock<State>>>,
+    Extension(shutdown): Extension<watch::Receiver<()>>,
+) -> Result<Response, AppError> {
+    let token = match (&bearer, &params.token) {
+        (Some(TypedHeader(Authorization(bearer))), _) => bearer.token(),
+        (None, Some(token)) => token,
+        (None, None) => return Err(ErrorKind::InvalidSession.into()),
+    };
+    let username = crate::session_user(&state, token)?;
+    let token = token.to_string();
+
+    Ok(upgrade
+        .on_upgrade(move |socket| stream(socket, state, shutdown, username, token, params.after)))
+}
+
+async fn stream(
+    mut socket: WebSocket,
+    state: Arc<RwLock<State>>,
+    mut shutdown: watch::Receiver<()>,
+    username: String,
+    token: String,
+    after: u64,
+) {
+    let (mut events, backlog) = {
+        let state = state.read();
+        let backlog = state
+            .inbox(&username, after, usize::MAX)
+            .into_iter()
+            .map(|message| state.replay_event(message, &username))
+            .collect::<Vec<_>>();
+        (state.subscribe(), backlog)
+    };
+
+    // Recorded as one batch, so a long backlog doesn't flood authors with receipt events.
+    let mut replayed = Vec::new();
+    for event in backlog {
+        if send_event(&mut socket, &event).await.is_err() {
+            delivered(&state, &username, &replayed);
+            return;
+        }
+        replayed.extend(event.message().cloned());
+    }
+    delivered(&state, &username, &replayed);
+
+    let mut ping = tokio::time::interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    let mut last_seen = Instant::now();
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => last_seen = Instant::now(),
+            },
+            event = events.recv() => match event {
+                Ok(event) if event.visible_to(&username) => {
+                    if !session_valid(&state, &token, &username) {
+                        close_invalid_session(&mut socket).await;
+                        return;
+                    }
+                    let event = match event {
+                        Event::Reply { message, parent } if !parent.involves(&username) => {
+                            Event::Message { message }
+                        }
+                        event => event,
+                    };
+                    if send_event(&mut socket, &event).await.is_err() {
+                        return;
+                    }
+                    if let Some(message) = event.message() {
+                        delivered(&state, &username, std::slice::from_ref(message));
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Closing lagging stream for {}, skipped {} events", username, skipped);
+                    let _ = socket.send(WsMessage::Close(None)).await;
+                    return;
+                }
+                Err(RecvError::Closed) => return,
+            },
+            _ = shutdown.changed() => {
+                let _ = socket
+                    .send(WsMessage::Close(Some(CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "Server shutting down".into(),
+                    })))
+                    .await;
+                return;
+            },
+            _ = ping.tick() => {
+                // Logging out or expiring doesn't touch open streams, so idle ones notice here.
+                if !session_valid(&state, &token, &username) {
+                    close_invalid_session(&mut socket).await;
+                    return;
+                }
+                if last_seen.elapsed() > PONG_TIMEOUT
+                    || socket.send(WsMessage::Ping(Vec::new())).await.is_err()
+                {
+                    return;
+                }
+            },
+        }
+    }
+}
+
+fn session_valid(state: &RwLock<State>, token: &str, username: &str) -> bool {
+    matches!(state.read().session_user(token), Ok(Some(user)) if user == username)
+}
+
+async fn close_invalid_session(socket: &mut WebSocket) {
+    let _ = socket
+        .send(WsMessage::Close(Some(CloseFrame {
+            code: close_code::POLICY,
+            reason: ErrorKind::InvalidSession.to_string().into(),
+        })))
+        .await;
+}
+
+fn delivered(state: &RwLock<State>, username: &str, messages: &[Arc<Message>]) {
+    if messages.is_empty() {
+        return;
+    }
+    if let Err(err) = state.write().mark_delivered(username, messages) {
+        tracing::warn!("Error recording delivery to {}: {}", username, err);
+    }
+}
+
+async fn send_event(socket: &mut WebSocket, event: &Event) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(event).expect("events always serialize");
+    socket.send(WsMessage::Text(text)).await
+}